@@ -0,0 +1,137 @@
+// Draws a large generated level for a fixed number of frames while the player
+// walks about, then prints how long the frames took:
+// cargo run --release --example benchmark -- [--uncached]
+// With --uncached the texture cache is emptied before every frame, so images
+// are read and batches built again each time the way drawing used to work.
+use std::env;
+use std::path;
+use std::time::{Duration, Instant};
+
+use ggez::{conf, event, graphics, timer, Context, GameResult};
+use specs::{Join, RunNow, WorldExt};
+
+use sokoban::actions::Action;
+use sokoban::collection::{Collection, LevelInfo};
+use sokoban::components::{BoxColour, Direction};
+use sokoban::errors::Location;
+use sokoban::layout::{self, Layout};
+use sokoban::levels;
+use sokoban::map::LevelMap;
+use sokoban::resources::{InputQueue, LevelLoadError, MapSize, Time};
+use sokoban::simulation::Simulation;
+use sokoban::systems;
+use sokoban::textures::{self, TextureCache};
+
+const WIDTH: u8 = 120;
+const HEIGHT: u8 = 80;
+const FRAMES: u32 = 600;
+// Frames between two steps of the player
+const STEP_EVERY: u32 = 10;
+
+// Walls around and dotted about, rows of boxes with their spots right under
+// them and a free corridor along the top for the player
+fn large_level() -> LevelMap {
+    let mut level_map = LevelMap::new(WIDTH, HEIGHT, Location::new("benchmark", 1, 1));
+    let colours: Vec<BoxColour> = BoxColour::all().collect();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let colour = colours[x as usize / 3 % colours.len()];
+            let tile = level_map.tile_mut(x, y);
+            tile.floor = true;
+            if x == 0 || y == 0 || x == WIDTH - 1 || y == HEIGHT - 1 || (x % 8 == 0 && y % 4 == 2) {
+                tile.wall = true;
+            } else if x % 3 == 1 && y % 4 == 3 {
+                tile.box_colour = Some(colour);
+            } else if x % 3 == 1 && y % 4 == 0 && y > 0 {
+                tile.spot_colour = Some(colour);
+            }
+        }
+    }
+    level_map.tile_mut(1, 1).player = true;
+    level_map
+}
+
+struct Benchmark {
+    simulation: Simulation,
+    uncached: bool,
+    frames: u32,
+    // Time spent in the rendering system
+    drawing: Duration,
+    started: Instant,
+}
+
+impl event::EventHandler for Benchmark {
+    fn update(&mut self, context: &mut Context) -> GameResult {
+        self.simulation.world.write_resource::<Time>().delta += timer::delta(context);
+
+        // Back and forth along the corridor, so the player's batch changes
+        if self.frames.is_multiple_of(STEP_EVERY) {
+            let step = self.frames / STEP_EVERY;
+            let direction = if step % 40 < 20 { Direction::Right } else { Direction::Left };
+            self.simulation.world.write_resource::<InputQueue>().actions.push(Action::Move(direction));
+        }
+        self.simulation.update();
+        Ok(())
+    }
+
+    fn draw(&mut self, context: &mut Context) -> GameResult {
+        if self.uncached {
+            self.simulation.world.write_resource::<TextureCache>().clear();
+        }
+        let start = Instant::now();
+        for missing in textures::update_textures(&mut self.simulation.world, context) {
+            println!("Cannot load {}", missing);
+        }
+        systems::RenderingSystem { context }.run_now(&self.simulation.world);
+        self.drawing += start.elapsed();
+        self.frames += 1;
+
+        if self.frames == FRAMES {
+            let world = &self.simulation.world;
+            let entities = world.entities().join().count();
+            let frames = FRAMES as f64;
+            println!(
+                "{} frames of a {}x{} level with {} entities{}:",
+                FRAMES, WIDTH, HEIGHT, entities, if self.uncached { ", uncached" } else { "" }
+            );
+            println!("  {:.2} ms per frame", self.started.elapsed().as_secs_f64() * 1000.0 / frames);
+            println!("  {:.2} ms drawing", self.drawing.as_secs_f64() * 1000.0 / frames);
+            println!("  {} batches rebuilt", world.read_resource::<TextureCache>().rebuilds);
+            event::quit(context);
+        }
+        Ok(())
+    }
+}
+
+fn main() -> GameResult {
+    let uncached = env::args().any(|arg| arg == "--uncached");
+
+    let mut simulation = Simulation::default();
+    let collection = Collection {
+        name: "benchmark".to_string(),
+        title: "Benchmark".to_string(),
+        author: None,
+        description: None,
+        levels: vec![LevelInfo::new("Warehouse".to_string(), Ok(large_level()))],
+    };
+    levels::start_collection(&mut simulation.world, collection, 0);
+    if let Some(error) = &simulation.world.read_resource::<LevelLoadError>().error {
+        println!("{}", error);
+        return Ok(());
+    }
+
+    let dimensions = layout::window_size(&simulation.world.read_resource::<MapSize>());
+    simulation.world.write_resource::<Layout>().window = dimensions;
+    simulation.world.insert(TextureCache::default());
+
+    // No vsync, frames come as fast as they can be drawn
+    let context_builder = ggez::ContextBuilder::new("rust_sokoban_benchmark", "sokoban")
+        .window_setup(conf::WindowSetup::default().title("Rust Sokoban benchmark").vsync(false))
+        .window_mode(conf::WindowMode::default().dimensions(dimensions.0, dimensions.1))
+        .add_resource_path(path::PathBuf::from("./resources"));
+    let (context, event_loop) = &mut context_builder.build()?;
+    graphics::set_default_filter(context, graphics::FilterMode::Nearest);
+
+    let benchmark = &mut Benchmark { simulation, uncached, frames: 0, drawing: Duration::ZERO, started: Instant::now() };
+    event::run(context, event_loop, benchmark)
+}
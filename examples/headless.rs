@@ -0,0 +1,37 @@
+// Plays a level from the solver's solution without opening a window:
+// cargo run --example headless -- [COLLECTION] [LEVEL_NUMBER]
+use std::env;
+
+use sokoban::actions::Action;
+use sokoban::components::Direction;
+use sokoban::map;
+use sokoban::simulation::Simulation;
+use sokoban::solver::{self, SolverMode, SolverResult};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let collection = args.get(1).map(String::as_str).unwrap_or("default");
+    let level = args.get(2).and_then(|number| number.parse::<usize>().ok()).unwrap_or(1).saturating_sub(1);
+
+    let mut simulation = match Simulation::new(collection, level) {
+        Ok(simulation) => simulation,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
+
+    let solution = match solver::solve(&map::current_level_map(&simulation.world), SolverMode::Coloured) {
+        SolverResult::Solved(solution) => solution,
+        _ => {
+            println!("No solution found");
+            return;
+        }
+    };
+
+    for (direction, _push) in solution.moves.chars().filter_map(Direction::from_lurd) {
+        let events = simulation.step(Action::Move(direction));
+        println!("{} -> {:?}", direction, events);
+    }
+    println!("{} after {} moves: {}", simulation.state(), simulation.moves_count(), simulation.lurd());
+}
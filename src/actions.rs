@@ -0,0 +1,58 @@
+use crate::components::Direction;
+
+// Everything a player (or a bot) can ask the game to do, whatever the input device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    // Step, pushing whatever is in the way
+    Move(Direction),
+
+    Undo,
+    Redo,
+    Restart,
+    NextLevel,
+    PreviousLevel,
+
+    // Ask the solver for the next move
+    Hint,
+
+    // Watch the moves made so far again from the start of the level
+    ReplayMoves,
+
+    // Playback controls while a replay runs, going back stops it
+    ReplayPause,
+    ReplayStep,
+    ReplayFaster,
+    ReplaySlower,
+
+    // Menu navigation, moving up and down picks an entry. Going back while
+    // playing pauses the game.
+    Confirm,
+    Back,
+
+    // Tile under the mouse when a button was pressed
+    Click { x: u8, y: u8 },
+
+    // Camera: switch between following, panning and fitting the level
+    NextCameraMode,
+    ZoomIn,
+    ZoomOut,
+    Pan(Direction),
+
+    // Switch between the window and the whole screen, done by the front end
+    ToggleFullscreen,
+
+    // Draw the tiles with the next skin under resources/skins
+    NextSkin,
+
+    // Level editor: the cursor moves with Move, brushes are counted from 0
+    Paint,
+    SelectBrush(usize),
+    NextBrush,
+    Grow(Direction),
+    Shrink(Direction),
+    NewLevel,
+    Validate,
+    TestPlay,
+    SaveLevel,
+    SaveXsb,
+}
@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::components::Motion;
+use crate::config::AnimationConfig;
+
+// How a move speeds up and slows down between two tiles
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // Share of the way covered after the given share of the time
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => 1.0 - 2.0 * (1.0 - t) * (1.0 - t),
+        }
+    }
+}
+
+// Sprite frames shown for every tile walked
+pub const FRAMES_PER_STEP: usize = 2;
+
+// Length and easing of moves. Headless runs have no clock going, so moves
+// are instant unless the front end sets a duration.
+pub struct Animation {
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self { duration: Duration::from_millis(0), easing: Easing::Linear }
+    }
+}
+
+impl Animation {
+    pub fn new(config: &AnimationConfig) -> Self {
+        Self { duration: Duration::from_millis(config.duration_ms), easing: config.easing }
+    }
+
+    // Share of the motion done at the given time, from 0 to 1
+    pub fn progress(&self, motion: &Motion, now: Duration) -> f32 {
+        if self.duration.as_millis() == 0 {
+            return 1.0;
+        }
+        (now.saturating_sub(motion.started_at).as_secs_f32() / self.duration.as_secs_f32()).min(1.0)
+    }
+
+    pub fn is_playing(&self, motion: &Motion, now: Duration) -> bool {
+        self.progress(motion, now) < 1.0
+    }
+
+    // Tile coordinates to draw the entity at and the sprite frame to use
+    pub fn place(&self, motion: &Motion, to: (u8, u8), now: Duration) -> (f32, f32, usize) {
+        let progress = self.progress(motion, now);
+        let eased = self.easing.apply(progress);
+        let x = motion.from.0 as f32 + (to.0 as f32 - motion.from.0 as f32) * eased;
+        let y = motion.from.1 as f32 + (to.1 as f32 - motion.from.1 as f32) * eased;
+        let walked = if progress < 1.0 { (progress * FRAMES_PER_STEP as f32) as usize } else { 0 };
+        (x, y, motion.steps * FRAMES_PER_STEP + walked)
+    }
+}
@@ -1,26 +1,42 @@
 use audio::SoundSource;
 use ggez::{audio, Context};
-use specs::{World, WorldExt};
+use specs::World;
 use std::collections::HashMap;
 
+use crate::events::{BoxPlacedOnSpot, Event};
+
 #[derive(Default)]
 pub struct AudioStore {
     pub sounds: HashMap<String, audio::Source>,
 }
 
 impl AudioStore {
+    // Nothing is loaded when running without an audio device, stay quiet then
     pub fn play_sound(&mut self, sound: &String) {
-        let _ = self
-            .sounds
-            .get_mut(sound)
-            .expect("expected sound")
-            .play_detached();
+        if let Some(source) = self.sounds.get_mut(sound) {
+            let _ = source.play_detached();
+        }
+    }
+
+    // Plays what the events of a frame sound like
+    pub fn play_events(&mut self, events: &[Event]) {
+        for event in events {
+            let sound = match event {
+                Event::PlayerHitObstacle => "wall",
+                Event::BoxPlacedOnSpot(BoxPlacedOnSpot { is_correct_spot: true }) => "correct",
+                Event::BoxPlacedOnSpot(BoxPlacedOnSpot { is_correct_spot: false }) => "incorrect",
+                Event::Deadlocked => "deadlock",
+                Event::PlayerWon => "won",
+                Event::EntityMoved(_) => continue,
+            };
+            self.play_sound(&sound.to_string());
+        }
     }
 }
 
 pub fn initialize_sounds(world: &mut World, context: &mut Context) {
-    let mut audio_store = world.write_resource::<AudioStore>();
-    let sounds = ["correct", "incorrect", "wall", "won"];
+    let mut audio_store = AudioStore::default();
+    let sounds = ["correct", "incorrect", "wall", "won", "deadlock"];
 
     for sound in sounds.iter() {
         let sound_name = sound.to_string();
@@ -29,4 +45,5 @@ pub fn initialize_sounds(world: &mut World, context: &mut Context) {
 
         audio_store.sounds.insert(sound_name, sound_source);
     }
-}
\ No newline at end of file
+    world.insert(audio_store);
+}
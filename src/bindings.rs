@@ -0,0 +1,462 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+
+use crate::actions::Action;
+use crate::components::Direction;
+use crate::config::Config;
+
+// Actions that can be put on keys and gamepad buttons: name in the config file, label on the
+// controls screen and the action itself
+pub const BINDABLE_ACTIONS: [(&str, &str, Action); 26] = [
+    ("move_up", "Move up", Action::Move(Direction::Up)),
+    ("move_down", "Move down", Action::Move(Direction::Down)),
+    ("move_left", "Move left", Action::Move(Direction::Left)),
+    ("move_right", "Move right", Action::Move(Direction::Right)),
+    ("undo", "Undo", Action::Undo),
+    ("redo", "Redo", Action::Redo),
+    ("restart", "Restart", Action::Restart),
+    ("hint", "Hint", Action::Hint),
+    ("pause", "Pause / back", Action::Back),
+    ("confirm", "Confirm", Action::Confirm),
+    ("next_level", "Next level", Action::NextLevel),
+    ("previous_level", "Previous level", Action::PreviousLevel),
+    ("replay", "Replay moves", Action::ReplayMoves),
+    ("replay_pause", "Pause replay", Action::ReplayPause),
+    ("replay_step", "Replay one step", Action::ReplayStep),
+    ("replay_faster", "Replay faster", Action::ReplayFaster),
+    ("replay_slower", "Replay slower", Action::ReplaySlower),
+    ("camera_mode", "Camera mode", Action::NextCameraMode),
+    ("zoom_in", "Zoom in", Action::ZoomIn),
+    ("zoom_out", "Zoom out", Action::ZoomOut),
+    ("pan_up", "Pan up", Action::Pan(Direction::Up)),
+    ("pan_down", "Pan down", Action::Pan(Direction::Down)),
+    ("pan_left", "Pan left", Action::Pan(Direction::Left)),
+    ("pan_right", "Pan right", Action::Pan(Direction::Right)),
+    ("fullscreen", "Fullscreen", Action::ToggleFullscreen),
+    ("skin", "Next skin", Action::NextSkin),
+];
+
+// Actions of the level editor, bound apart from the ones above since they share
+// keys with them. Grow and shrink are the cursor keys with Shift and Ctrl.
+pub const EDITOR_ACTIONS: [(&str, &str, Action); 22] = [
+    ("cursor_up", "Cursor up", Action::Move(Direction::Up)),
+    ("cursor_down", "Cursor down", Action::Move(Direction::Down)),
+    ("cursor_left", "Cursor left", Action::Move(Direction::Left)),
+    ("cursor_right", "Cursor right", Action::Move(Direction::Right)),
+    ("paint", "Paint", Action::Paint),
+    ("next_brush", "Next brush", Action::NextBrush),
+    ("brush_1", "Brush 1", Action::SelectBrush(0)),
+    ("brush_2", "Brush 2", Action::SelectBrush(1)),
+    ("brush_3", "Brush 3", Action::SelectBrush(2)),
+    ("brush_4", "Brush 4", Action::SelectBrush(3)),
+    ("brush_5", "Brush 5", Action::SelectBrush(4)),
+    ("brush_6", "Brush 6", Action::SelectBrush(5)),
+    ("brush_7", "Brush 7", Action::SelectBrush(6)),
+    ("brush_8", "Brush 8", Action::SelectBrush(7)),
+    ("brush_9", "Brush 9", Action::SelectBrush(8)),
+    ("brush_10", "Brush 10", Action::SelectBrush(9)),
+    ("new_level", "New level", Action::NewLevel),
+    ("validate", "Check level", Action::Validate),
+    ("test_play", "Test play", Action::TestPlay),
+    ("save_level", "Save level", Action::SaveLevel),
+    ("save_xsb", "Save as XSB", Action::SaveXsb),
+    ("back", "Back", Action::Back),
+];
+
+// Keys that can be bound, named like the key codes of ggez so the front end can
+// tell them apart by name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    A, B, C, D, E, F, G,
+    H, I, J, K, L, M, N,
+    O, P, Q, R, S, T, U,
+    V, W, X, Y, Z,
+    Key0, Key1, Key2, Key3, Key4,
+    Key5, Key6, Key7, Key8, Key9,
+    F1, F2, F3, F4, F5, F6,
+    F7, F8, F9, F10, F11, F12,
+    Up, Down, Left, Right,
+    Space, Return, Escape, Tab, Back,
+    Insert, Delete, Home, End, PageUp, PageDown,
+    Minus, Equals, Period, Comma, Slash,
+    Backslash, Semicolon, Apostrophe, LBracket, RBracket,
+    Grave, Add, Subtract, Multiply, Divide,
+    NumpadEnter, Numpad0, Numpad1, Numpad2, Numpad3,
+    Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+}
+
+// Keys that can be bound, by the name they have in the config file
+const KEYS: [Key; 89] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G,
+    Key::H, Key::I, Key::J, Key::K, Key::L, Key::M, Key::N,
+    Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U,
+    Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4,
+    Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6,
+    Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    Key::Up, Key::Down, Key::Left, Key::Right,
+    Key::Space, Key::Return, Key::Escape, Key::Tab, Key::Back,
+    Key::Insert, Key::Delete, Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::Minus, Key::Equals, Key::Period, Key::Comma, Key::Slash,
+    Key::Backslash, Key::Semicolon, Key::Apostrophe, Key::LBracket, Key::RBracket,
+    Key::Grave, Key::Add, Key::Subtract, Key::Multiply, Key::Divide,
+    Key::NumpadEnter, Key::Numpad0, Key::Numpad1, Key::Numpad2, Key::Numpad3,
+    Key::Numpad4, Key::Numpad5, Key::Numpad6, Key::Numpad7, Key::Numpad8, Key::Numpad9,
+];
+
+// Gamepad buttons that can be bound, named like the ones of ggez
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    South, East, North, West, C, Z,
+    LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2,
+    Select, Start, Mode, LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight,
+}
+
+// Gamepad buttons that can be bound
+const BUTTONS: [Button; 19] = [
+    Button::South, Button::East, Button::North, Button::West, Button::C, Button::Z,
+    Button::LeftTrigger, Button::LeftTrigger2, Button::RightTrigger, Button::RightTrigger2,
+    Button::Select, Button::Start, Button::Mode, Button::LeftThumb, Button::RightThumb,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+
+// Keys and buttons go by their names in the code
+pub fn input_name<T: Debug>(input: T) -> String {
+    format!("{:?}", input)
+}
+
+fn parse_input<T: Copy + Debug>(known: &[T], name: &str) -> Option<T> {
+    known.iter().copied().find(|input| input_name(*input).eq_ignore_ascii_case(name))
+}
+
+// Bindable key or button of the given name, the front end finds its own key
+// codes and buttons this way
+pub fn key(name: &str) -> Option<Key> {
+    parse_input(&KEYS, name)
+}
+
+pub fn button(name: &str) -> Option<Button> {
+    parse_input(&BUTTONS, name)
+}
+// Keys of every bindable action, in the order of BINDABLE_ACTIONS. Arrows and
+// WASD move, H asks for a hint. With vi keys hjkl move too and the hint is
+// left on / and F1.
+fn default_keys(vi_keys: bool) -> Vec<Vec<Key>> {
+    let vi = |key: Key| if vi_keys { vec![key] } else { vec![] };
+    vec![
+        [vec![Key::Up, Key::W], vi(Key::K)].concat(),
+        [vec![Key::Down, Key::S], vi(Key::J)].concat(),
+        [vec![Key::Left, Key::A], vi(Key::H)].concat(),
+        [vec![Key::Right, Key::D], vi(Key::L)].concat(),
+        vec![Key::Z, Key::U, Key::Back],
+        vec![Key::Y],
+        vec![Key::R],
+        [if vi_keys { vec![] } else { vec![Key::H] }, vec![Key::Slash, Key::F1]].concat(),
+        vec![Key::Escape],
+        vec![Key::Return, Key::NumpadEnter],
+        vec![Key::N, Key::PageDown],
+        vec![Key::P, Key::PageUp],
+        vec![Key::V],
+        vec![Key::Space],
+        vec![Key::Period],
+        vec![Key::Equals, Key::Add],
+        vec![Key::Minus, Key::Subtract],
+        vec![Key::C],
+        vec![Key::RBracket],
+        vec![Key::LBracket],
+        vec![Key::Numpad8],
+        vec![Key::Numpad2],
+        vec![Key::Numpad4],
+        vec![Key::Numpad6],
+        vec![Key::F11],
+        vec![Key::F2],
+    ]
+}
+
+// Keys of the editor actions, in the order of EDITOR_ACTIONS
+fn default_editor_keys() -> Vec<Vec<Key>> {
+    vec![
+        vec![Key::Up],
+        vec![Key::Down],
+        vec![Key::Left],
+        vec![Key::Right],
+        vec![Key::Space, Key::Return],
+        vec![Key::Tab],
+        vec![Key::Key1],
+        vec![Key::Key2],
+        vec![Key::Key3],
+        vec![Key::Key4],
+        vec![Key::Key5],
+        vec![Key::Key6],
+        vec![Key::Key7],
+        vec![Key::Key8],
+        vec![Key::Key9],
+        vec![Key::Key0],
+        vec![Key::N],
+        vec![Key::V],
+        vec![Key::T],
+        vec![Key::S],
+        vec![Key::X],
+        vec![Key::Escape],
+    ]
+}
+
+// Buttons in the same order. The d-pad moves; A confirms and B goes back in the
+// menus, Start pauses.
+fn default_buttons() -> Vec<Vec<Button>> {
+    vec![
+        vec![Button::DPadUp],
+        vec![Button::DPadDown],
+        vec![Button::DPadLeft],
+        vec![Button::DPadRight],
+        vec![Button::West, Button::LeftTrigger],
+        vec![Button::RightTrigger],
+        vec![Button::North],
+        vec![Button::Select],
+        vec![Button::Start, Button::East],
+        vec![Button::South],
+        vec![Button::RightTrigger2],
+        vec![Button::LeftTrigger2],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![Button::RightThumb],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+    ]
+}
+
+// Defaults with the bindings of the config file on top, names that are not
+// known are noted in the warnings and left out. An input given in the file is taken off the
+// actions that only have their defaults.
+fn configured<T: Copy + Debug + PartialEq>(
+    actions: &[(&str, &str, Action)],
+    mut bound: Vec<Vec<T>>,
+    config: &BTreeMap<String, Vec<String>>,
+    known: &[T],
+    kind: &str,
+    warnings: &mut Vec<String>,
+) -> Vec<Vec<T>> {
+    let mut configured = Vec::new();
+    for (name, input_names) in config.iter() {
+        let index = match actions.iter().position(|(action_name, _, _)| action_name == name) {
+            Some(index) => index,
+            None => {
+                warnings.push(format!("Unknown action in config: {}", name));
+                continue;
+            }
+        };
+        bound[index] = input_names
+            .iter()
+            .filter_map(|input_name| {
+                let input = parse_input(known, input_name);
+                if input.is_none() {
+                    warnings.push(format!("Unknown {} for {} in config: {}", kind, name, input_name));
+                }
+                input
+            })
+            .collect();
+        configured.push(index);
+    }
+
+    let taken: Vec<T> = configured.iter().flat_map(|index| bound[*index].clone()).collect();
+    for (index, inputs) in bound.iter_mut().enumerate() {
+        if !configured.contains(&index) {
+            inputs.retain(|input| !taken.contains(input));
+        }
+    }
+    bound
+}
+
+fn config_table<T: Copy + Debug>(actions: &[(&str, &str, Action)], bound: &[Vec<T>]) -> BTreeMap<String, Vec<String>> {
+    actions
+        .iter()
+        .zip(bound.iter())
+        .map(|((name, _, _), inputs)| (name.to_string(), inputs.iter().map(|input| input_name(*input)).collect()))
+        .collect()
+}
+
+// Adds the input to the action, taking it away from any other one, or takes
+// it away from the action when it already has it
+fn toggle<T: PartialEq>(bound: &mut [Vec<T>], index: usize, input: T) {
+    if bound[index].contains(&input) {
+        bound[index].retain(|known| *known != input);
+    } else {
+        for inputs in bound.iter_mut() {
+            inputs.retain(|known| *known != input);
+        }
+        bound[index].push(input);
+    }
+}
+
+// What each key and button does, and each key in the level editor
+pub struct Bindings {
+    pub keys: Vec<Vec<Key>>,
+    pub buttons: Vec<Vec<Button>>,
+    pub editor_keys: Vec<Vec<Key>>,
+    // Key or button pressed on the controls screen while it waits for one
+    pub captured_key: Option<Key>,
+    pub captured_button: Option<Button>,
+    // Defaults include hjkl, see default_keys
+    pub vi_keys: bool,
+    // Set when the bindings need writing to the config file
+    pub changed: bool,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self {
+            keys: default_keys(false),
+            buttons: default_buttons(),
+            editor_keys: default_editor_keys(),
+            captured_key: None,
+            captured_button: None,
+            vi_keys: false,
+            changed: false,
+        }
+    }
+}
+
+impl Bindings {
+    // The bindings, and the names in the config file that are not known
+    pub fn from_config(config: &Config) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let bindings = Self {
+            keys: configured(
+                &BINDABLE_ACTIONS,
+                default_keys(config.vi_keys),
+                &config.keys,
+                &KEYS,
+                "key",
+                &mut warnings,
+            ),
+            buttons: configured(
+                &BINDABLE_ACTIONS,
+                default_buttons(),
+                &config.gamepad.buttons,
+                &BUTTONS,
+                "button",
+                &mut warnings,
+            ),
+            editor_keys: configured(
+                &EDITOR_ACTIONS,
+                default_editor_keys(),
+                &config.editor_keys,
+                &KEYS,
+                "key",
+                &mut warnings,
+            ),
+            vi_keys: config.vi_keys,
+            ..Self::default()
+        };
+        (bindings, warnings)
+    }
+
+    // Writes every binding, so the file shows what can be changed
+    pub fn write_config(&self, config: &mut Config) {
+        config.keys = config_table(&BINDABLE_ACTIONS, &self.keys);
+        config.gamepad.buttons = config_table(&BINDABLE_ACTIONS, &self.buttons);
+        config.editor_keys = config_table(&EDITOR_ACTIONS, &self.editor_keys);
+    }
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.keys.iter().position(|keys| keys.contains(&key)).map(|index| BINDABLE_ACTIONS[index].2)
+    }
+
+    // Name of the first key of an action, None when it has none
+    pub fn key_name(&self, action: Action) -> Option<String> {
+        let index = BINDABLE_ACTIONS.iter().position(|(_, _, bound)| *bound == action)?;
+        self.keys[index].first().map(|key| input_name(*key))
+    }
+
+    // The editor action of a key, cursor keys grow the map with Shift and
+    // shrink it with Ctrl
+    pub fn editor_action(&self, key: Key, shift: bool, ctrl: bool) -> Option<Action> {
+        let index = self.editor_keys.iter().position(|keys| keys.contains(&key))?;
+        let action = match EDITOR_ACTIONS[index].2 {
+            Action::Move(direction) if shift => Action::Grow(direction),
+            Action::Move(direction) if ctrl => Action::Shrink(direction),
+            action => action,
+        };
+        Some(action)
+    }
+
+    // Name of the first key of an editor action
+    pub fn editor_key_name(&self, action: Action) -> Option<String> {
+        let index = EDITOR_ACTIONS.iter().position(|(_, _, bound)| *bound == action)?;
+        self.editor_keys[index].first().map(|key| input_name(*key))
+    }
+
+    pub fn button_action(&self, button: Button) -> Option<Action> {
+        self.buttons.iter().position(|buttons| buttons.contains(&button)).map(|index| BINDABLE_ACTIONS[index].2)
+    }
+
+    // Binds whatever was pressed while the controls screen waited, true once
+    // something was
+    pub fn bind_captured(&mut self, index: usize) -> bool {
+        if let Some(key) = self.captured_key.take() {
+            toggle(&mut self.keys, index, key);
+        } else if let Some(button) = self.captured_button.take() {
+            toggle(&mut self.buttons, index, button);
+        } else {
+            return false;
+        }
+        self.changed = true;
+        true
+    }
+
+    pub fn reset(&mut self) {
+        self.keys = default_keys(self.vi_keys);
+        self.buttons = default_buttons();
+        self.editor_keys = default_editor_keys();
+        self.changed = true;
+    }
+
+    // Keys and buttons of the action for the screen, like "Up, W, DPadUp"
+    pub fn describe(&self, index: usize) -> String {
+        let keys = self.keys[index].iter().map(|key| input_name(*key));
+        let buttons = self.buttons[index].iter().map(|button| input_name(*button));
+        keys.chain(buttons).collect::<Vec<_>>().join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editor_keys_grow_and_shrink_with_modifiers() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.editor_action(Key::Up, false, false), Some(Action::Move(Direction::Up)));
+        assert_eq!(bindings.editor_action(Key::Up, true, false), Some(Action::Grow(Direction::Up)));
+        assert_eq!(bindings.editor_action(Key::Left, false, true), Some(Action::Shrink(Direction::Left)));
+        assert_eq!(bindings.editor_action(Key::Key0, true, false), Some(Action::SelectBrush(9)));
+        assert_eq!(bindings.editor_action(Key::H, false, false), None);
+    }
+
+    #[test]
+    fn editor_keys_from_config() {
+        let mut config = Config::default();
+        config.editor_keys.insert("save_level".to_string(), vec!["F5".to_string()]);
+        config.editor_keys.insert("paint".to_string(), vec!["S".to_string(), "Nope".to_string()]);
+        let (bindings, warnings) = Bindings::from_config(&config);
+
+        assert_eq!(bindings.editor_action(Key::F5, false, false), Some(Action::SaveLevel));
+        assert_eq!(bindings.editor_action(Key::S, false, false), Some(Action::Paint));
+        assert_eq!(bindings.editor_key_name(Action::SaveLevel), Some("F5".to_string()));
+        assert_eq!(warnings, vec!["Unknown key for paint in config: Nope".to_string()]);
+        // Game keys are bound apart
+        assert_eq!(bindings.action(Key::S), Some(Action::Move(Direction::Down)));
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use specs::{Join, World, WorldExt};
+
+use crate::actions::Action;
+use crate::animation::Animation;
+use crate::components::{Motion, Player, Position};
+use crate::config::CameraConfig;
+use crate::constants::{MAX_ZOOM, MIN_ZOOM, TILE_SIZE};
+use crate::editor::Editor;
+use crate::layout::Rect;
+use crate::resources::{MapSize, Time};
+use crate::screens::{Screen, Screens};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CameraMode {
+    // Keeps the player in the middle, as far as the level goes
+    Follow,
+    // Stays where the player moved it
+    Pan,
+    // Shows the whole level as large as it fits
+    Fit,
+}
+
+impl CameraMode {
+    pub fn next(self) -> Self {
+        match self {
+            CameraMode::Follow => CameraMode::Fit,
+            CameraMode::Fit => CameraMode::Pan,
+            CameraMode::Pan => CameraMode::Follow,
+        }
+    }
+}
+
+impl Display for CameraMode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            CameraMode::Follow => "follow",
+            CameraMode::Pan => "pan",
+            CameraMode::Fit => "fit",
+        })
+    }
+}
+
+// What part of the level is shown and how large
+pub struct Camera {
+    pub mode: CameraMode,
+    // Scale asked for when following or panning
+    pub zoom: f32,
+    // Scales of 1 and up are rounded down to whole pixels per pixel
+    pub integer_scaling: bool,
+    // Tile at the middle of the view, kept while panning
+    pub centre: (f32, f32),
+    // Part of the window the board is drawn in, in pixels
+    pub viewport: Rect,
+    // Worked out every frame: scale and where the top left of the map goes
+    pub scale: f32,
+    pub origin: (f32, f32),
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(&CameraConfig::default())
+    }
+}
+
+impl Camera {
+    pub fn new(config: &CameraConfig) -> Self {
+        Self {
+            mode: config.mode,
+            zoom: config.zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+            integer_scaling: config.integer_scaling,
+            centre: (0.0, 0.0),
+            viewport: Rect::default(),
+            scale: 1.0,
+            origin: (0.0, 0.0),
+        }
+    }
+
+    // Zooming in steps by whole scales with integer scaling, halving and
+    // doubling below 1
+    pub fn zoom_by(&mut self, steps: i32) {
+        let zoom = if self.mode == CameraMode::Fit { self.scale } else { self.zoom };
+        let zoom = if self.integer_scaling && (zoom > 1.0 || (zoom == 1.0 && steps > 0)) {
+            zoom.floor() + steps as f32
+        } else {
+            zoom * 2f32.powi(steps)
+        };
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+        if self.mode == CameraMode::Fit {
+            self.mode = CameraMode::Follow;
+        }
+    }
+
+    // Applies the camera actions, false for any other action
+    pub fn apply(&mut self, action: Action) -> bool {
+        match action {
+            Action::NextCameraMode => self.mode = self.mode.next(),
+            Action::ZoomIn => self.zoom_by(1),
+            Action::ZoomOut => self.zoom_by(-1),
+            Action::Pan(direction) => {
+                let (dx, dy) = direction.offset();
+                self.pan(dx as f32, dy as f32);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Moves the view by the given number of tiles and leaves it there
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.mode = CameraMode::Pan;
+        self.centre = (self.centre.0 + dx, self.centre.1 + dy);
+    }
+
+    // Pans by a distance in pixels, for dragging with the mouse
+    pub fn drag(&mut self, dx: f32, dy: f32) {
+        let tile = TILE_SIZE * self.scale;
+        self.pan(-dx / tile, -dy / tile);
+    }
+
+    // Works out the scale and origin for a map of the given size, the focus
+    // being the tile to follow
+    pub fn update(&mut self, map_size: &MapSize, focus: (f32, f32)) {
+        let map_width = map_size.width as f32 * TILE_SIZE;
+        let map_height = map_size.height as f32 * TILE_SIZE;
+        let view = self.viewport;
+
+        let scale = match self.mode {
+            CameraMode::Fit if view.w > 0.0 && view.h > 0.0 && map_width > 0.0 && map_height > 0.0 => {
+                (view.w / map_width).min(view.h / map_height)
+            }
+            _ => self.zoom,
+        };
+        self.scale = if self.integer_scaling && scale >= 1.0 { scale.floor() } else { scale };
+
+        self.centre = match self.mode {
+            CameraMode::Follow => (focus.0 + 0.5, focus.1 + 0.5),
+            CameraMode::Fit => (map_size.width as f32 / 2.0, map_size.height as f32 / 2.0),
+            CameraMode::Pan => (
+                self.centre.0.clamp(0.0, map_size.width as f32),
+                self.centre.1.clamp(0.0, map_size.height as f32),
+            ),
+        };
+
+        // A map smaller than the view sits in its middle, a larger one
+        // scrolls no further than its edges
+        let axis = |start: f32, length: f32, size: f32, centre: f32| {
+            if size <= length {
+                start + (length - size) / 2.0
+            } else {
+                (start + length / 2.0 - centre * TILE_SIZE * self.scale).clamp(start + length - size, start)
+            }
+        };
+        let x = axis(view.x, view.w, map_width * self.scale, self.centre.0);
+        let y = axis(view.y, view.h, map_height * self.scale, self.centre.1);
+        self.origin = if self.integer_scaling { (x.round(), y.round()) } else { (x, y) };
+    }
+
+    // Tile under the given window position, None outside of the board
+    pub fn screen_to_tile(&self, map_size: &MapSize, x: f32, y: f32) -> Option<(u8, u8)> {
+        if !self.viewport.contains(x, y) || self.scale <= 0.0 {
+            return None;
+        }
+        let tile = TILE_SIZE * self.scale;
+        let (x, y) = (((x - self.origin.0) / tile).floor(), ((y - self.origin.1) / tile).floor());
+        if x < 0.0 || y < 0.0 || x >= map_size.width as f32 || y >= map_size.height as f32 {
+            return None;
+        }
+        Some((x as u8, y as u8))
+    }
+}
+
+// Points the camera at the player, or at the cursor in the editor
+pub fn update_camera(world: &mut World) {
+    let focus = if world.read_resource::<Screens>().current() == Screen::Editor {
+        let cursor = world.read_resource::<Editor>().cursor;
+        (cursor.0 as f32, cursor.1 as f32)
+    } else {
+        let animation = world.read_resource::<Animation>();
+        let now = world.read_resource::<Time>().delta;
+        let positions = world.read_storage::<Position>();
+        let players = world.read_storage::<Player>();
+        let motions = world.read_storage::<Motion>();
+        (&positions, &players, motions.maybe())
+            .join()
+            .next()
+            .map(|(position, _, motion)| match motion {
+                Some(motion) => {
+                    let (x, y, _) = animation.place(motion, (position.x, position.y), now);
+                    (x, y)
+                }
+                None => (position.x as f32, position.y as f32),
+            })
+            .unwrap_or_default()
+    };
+
+    let map_size = *world.read_resource::<MapSize>();
+    world.write_resource::<Camera>().update(&map_size, focus);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(mode: CameraMode, width: f32, height: f32) -> Camera {
+        let mut camera = Camera::new(&CameraConfig { mode, zoom: 1.0, integer_scaling: true });
+        camera.viewport = Rect::new(0.0, 0.0, width, height);
+        camera
+    }
+
+    fn map_size(width: u8, height: u8) -> MapSize {
+        MapSize { width, height }
+    }
+
+    #[test]
+    fn small_map_is_centred() {
+        let mut camera = camera(CameraMode::Follow, 320.0, 320.0);
+        camera.update(&map_size(4, 4), (0.0, 0.0));
+        assert_eq!((camera.scale, camera.origin), (1.0, (96.0, 96.0)));
+    }
+
+    #[test]
+    fn following_stops_at_the_edges() {
+        let mut camera = camera(CameraMode::Follow, 320.0, 320.0);
+        camera.update(&map_size(20, 20), (0.0, 0.0));
+        assert_eq!(camera.origin, (0.0, 0.0));
+        camera.update(&map_size(20, 20), (19.0, 19.0));
+        assert_eq!(camera.origin, (-320.0, -320.0));
+        camera.update(&map_size(20, 20), (9.0, 9.0));
+        assert_eq!(camera.origin, (-144.0, -144.0));
+    }
+
+    #[test]
+    fn fit_shows_the_whole_map() {
+        let mut camera = camera(CameraMode::Fit, 640.0, 320.0);
+        camera.update(&map_size(40, 10), (0.0, 0.0));
+        assert_eq!((camera.scale, camera.origin), (0.5, (0.0, 80.0)));
+
+        // Scales of 1 and up stay whole
+        camera.update(&map_size(4, 4), (0.0, 0.0));
+        assert_eq!((camera.scale, camera.origin), (2.0, (192.0, 32.0)));
+    }
+
+    #[test]
+    fn panning_stays_on_the_map() {
+        let mut camera = camera(CameraMode::Follow, 320.0, 320.0);
+        camera.pan(-5.0, 50.0);
+        camera.update(&map_size(20, 20), (10.0, 10.0));
+        assert_eq!(camera.mode, CameraMode::Pan);
+        assert_eq!(camera.centre, (0.0, 20.0));
+        assert_eq!(camera.origin, (0.0, -320.0));
+    }
+
+    #[test]
+    fn screen_to_tile_inside_the_board_only() {
+        let mut camera = camera(CameraMode::Follow, 320.0, 320.0);
+        let size = map_size(4, 4);
+        camera.update(&size, (0.0, 0.0));
+        assert_eq!(camera.screen_to_tile(&size, 96.0, 96.0), Some((0, 0)));
+        assert_eq!(camera.screen_to_tile(&size, 223.0, 130.0), Some((3, 1)));
+        // Beside the map, and outside of the viewport
+        assert_eq!(camera.screen_to_tile(&size, 95.0, 96.0), None);
+        assert_eq!(camera.screen_to_tile(&size, 224.0, 96.0), None);
+        assert_eq!(camera.screen_to_tile(&size, 400.0, 96.0), None);
+    }
+
+    #[test]
+    fn zooming_steps_whole_scales() {
+        let mut camera = camera(CameraMode::Fit, 320.0, 320.0);
+        camera.update(&map_size(4, 4), (0.0, 0.0));
+        camera.apply(Action::ZoomIn);
+        assert_eq!((camera.mode, camera.zoom), (CameraMode::Follow, 3.0));
+        camera.zoom = 1.0;
+        camera.apply(Action::ZoomOut);
+        assert_eq!(camera.zoom, 0.5);
+        camera.zoom = MAX_ZOOM;
+        camera.apply(Action::ZoomIn);
+        assert_eq!(camera.zoom, MAX_ZOOM);
+    }
+}
@@ -0,0 +1,458 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::errors::{LevelError, Location};
+use crate::map::{self, LevelMap};
+
+pub const LEVELS_DIRECTORY: &str = "./resources/levels";
+
+// Name of the collection made of the single level_NN files
+pub const DEFAULT_COLLECTION: &str = "default";
+
+// Extensions of files holding a whole collection of levels
+const COLLECTION_EXTENSIONS: [&str; 3] = ["sok", "txt", "slc"];
+
+#[derive(Clone)]
+pub struct LevelInfo {
+    pub title: String,
+    pub author: Option<String>,
+    pub comment: Option<String>,
+    pub difficulty: Option<String>,
+    // A broken level doesn't stop the rest of the collection from loading
+    pub map: Result<LevelMap, LevelError>,
+}
+
+impl LevelInfo {
+    pub fn new(title: String, map: Result<LevelMap, LevelError>) -> Self {
+        Self {
+            title,
+            author: None,
+            comment: None,
+            difficulty: None,
+            map,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Collection {
+    pub name: String,
+    pub title: String,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub levels: Vec<LevelInfo>,
+}
+
+impl Collection {
+    // Finds a level by its title, or by its 1-based number in the collection
+    pub fn find_level(&self, name: &str) -> Option<usize> {
+        self.levels
+            .iter()
+            .position(|level| level.title.eq_ignore_ascii_case(name))
+            .or_else(|| match name.parse::<usize>() {
+                Ok(number) if number >= 1 && number <= self.levels.len() => Some(number - 1),
+                _ => None,
+            })
+    }
+}
+
+fn collection_path(name: &str) -> Option<PathBuf> {
+    COLLECTION_EXTENSIONS
+        .iter()
+        .map(|extension| Path::new(LEVELS_DIRECTORY).join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
+
+// Lists the names of every collection that can be passed to load_collection
+pub fn list_collections() -> Vec<String> {
+    let mut names = vec![DEFAULT_COLLECTION.to_string()];
+    if let Ok(entries) = fs::read_dir(LEVELS_DIRECTORY) {
+        let mut packs = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| COLLECTION_EXTENSIONS.contains(&extension))
+            })
+            .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(String::from))
+            .collect::<Vec<_>>();
+        packs.sort();
+        names.append(&mut packs);
+    }
+    names
+}
+
+fn read_file(path: &Path) -> Result<String, LevelError> {
+    fs::read_to_string(path).map_err(|error| LevelError::Io {
+        file: path.display().to_string(),
+        message: error.to_string(),
+    })
+}
+
+pub fn load_collection(name: &str) -> Result<Collection, LevelError> {
+    let (mut collection, file) = if name == DEFAULT_COLLECTION {
+        (load_default_collection(), LEVELS_DIRECTORY.to_string())
+    } else {
+        let path = collection_path(name)
+            .ok_or_else(|| LevelError::NotFound { name: format!("collection {}", name) })?;
+        let file = path.display().to_string();
+        let source = read_file(&path)?;
+        let collection = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("slc") => parse_slc(&source, &file)?,
+            _ => parse_sok(&source, &file),
+        };
+        (collection, file)
+    };
+
+    if collection.levels.is_empty() {
+        return Err(LevelError::NoLevels { file });
+    }
+    collection.name = name.to_string();
+    Ok(collection)
+}
+
+// XSB uses '#' for walls, which never appears in our own format
+fn is_xsb(level_source: &str) -> bool {
+    level_source.contains('#')
+}
+
+// The default collection is every level_NN (or level_NN.xsb) file, in order
+fn load_default_collection() -> Collection {
+    let mut files = fs::read_dir(LEVELS_DIRECTORY)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter_map(|path| {
+                    let stem = path.file_stem()?.to_str()?;
+                    let number = stem.strip_prefix("level_")?.parse::<u32>().ok()?;
+                    match path.extension().and_then(|extension| extension.to_str()) {
+                        None | Some("xsb") => Some((number, path.clone())),
+                        _ => None,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    files.sort();
+
+    let levels = files
+        .iter()
+        .map(|(number, path)| {
+            let file = path.display().to_string();
+            let map = read_file(path).and_then(|level_source| {
+                if is_xsb(&level_source) {
+                    map::parse_xsb(&level_source, Location::new(&file, 1, 1))
+                } else {
+                    map::parse_map(&level_source, Location::new(&file, 1, 1))
+                }
+            });
+            LevelInfo::new(format!("Level {}", number), map)
+        })
+        .collect();
+
+    Collection {
+        name: DEFAULT_COLLECTION.to_string(),
+        title: "Rust Sokoban".to_string(),
+        levels,
+        ..Collection::default()
+    }
+}
+
+// Board rows are made of XSB items; rows starting with a wall are kept even
+// with unknown items so that parsing can report them
+fn is_board_line(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+        || (line.contains('#') && line.chars().all(|c| " #@+$*.-_".contains(c)))
+}
+
+// Splits a "Key: value" metadata line
+fn metadata(line: &str) -> Option<(String, String)> {
+    let separator = line.find(':')?;
+    let key = line[..separator].trim();
+    if key.is_empty() || key.contains(' ') {
+        return None;
+    }
+    Some((key.to_lowercase(), line[separator + 1..].trim().to_string()))
+}
+
+fn set_metadata(level: &mut LevelInfo, key: &str, value: String) {
+    match key {
+        "title" => level.title = value,
+        "author" => level.author = Some(value),
+        "comment" => level.comment = Some(value),
+        "difficulty" => level.difficulty = Some(value),
+        _ => (),
+    }
+}
+
+// Parses .sok and plain text packs: boards in XSB notation, each followed by
+// its "Title:", "Author:", "Comment:" and "Difficulty:" lines. A "; name" line
+// right before a board is also taken as that level's title, and metadata
+// before the first board describes the collection itself.
+pub fn parse_sok(source: &str, file: &str) -> Collection {
+    let mut collection = Collection::default();
+    let mut levels: Vec<LevelInfo> = Vec::new();
+    let mut board: Vec<&str> = Vec::new();
+    let mut board_line = 0;
+    let mut pending_title: Option<String> = None;
+    let mut in_comment = false;
+
+    let lines: Vec<&str> = source.lines().map(|line| line.trim_end()).collect();
+    for (index, line) in lines.iter().enumerate() {
+        if is_board_line(line) && !in_comment {
+            if board.is_empty() {
+                board_line = index + 1;
+            }
+            board.push(line);
+            let board_ends = lines.get(index + 1).is_none_or(|next| !is_board_line(next));
+            if board_ends {
+                let number = levels.len() + 1;
+                levels.push(LevelInfo::new(
+                    pending_title.take().unwrap_or_else(|| format!("Level {}", number)),
+                    map::parse_xsb(&board.join("\n"), Location::new(file, board_line, 1)),
+                ));
+                board.clear();
+            }
+            continue;
+        }
+
+        if in_comment {
+            if line.trim().eq_ignore_ascii_case("comment-end:") {
+                in_comment = false;
+            } else if let Some(level) = levels.last_mut() {
+                let comment = level.comment.get_or_insert_with(String::new);
+                if !comment.is_empty() {
+                    comment.push('\n');
+                }
+                comment.push_str(line.trim());
+            }
+            continue;
+        }
+
+        if let Some(name) = line.trim().strip_prefix(';') {
+            let board_follows = lines.get(index + 1).is_some_and(|next| is_board_line(next));
+            if board_follows && !name.trim().is_empty() {
+                pending_title = Some(name.trim().to_string());
+            }
+            continue;
+        }
+
+        if let Some((key, value)) = metadata(line) {
+            match levels.last_mut() {
+                Some(level) => {
+                    if key == "comment" && value.is_empty() {
+                        in_comment = true;
+                    } else {
+                        set_metadata(level, &key, value);
+                    }
+                }
+                None => match key.as_str() {
+                    "title" => collection.title = value,
+                    "author" => collection.author = Some(value),
+                    "description" | "comment" => collection.description = Some(value),
+                    _ => (),
+                },
+            }
+        }
+    }
+
+    collection.levels = levels;
+    collection
+}
+
+// Parses SLC, the XML collection format: <Title>, <Description> and a
+// <LevelCollection> of <Level Id=".." Copyright=".."> holding one <L> per row
+pub fn parse_slc(source: &str, file: &str) -> Result<Collection, LevelError> {
+    let mut collection = Collection::default();
+    let mut elements: Vec<String> = Vec::new();
+    let mut level: Option<LevelInfo> = None;
+    let mut rows: Vec<String> = Vec::new();
+    let mut rows_location = Location::default();
+
+    let mut reader = EventReader::from_str(source);
+    loop {
+        let event = reader.next().map_err(|error| LevelError::InvalidXml {
+            location: Location::new(file, error.position().row as usize + 1, error.position().column as usize + 1),
+            message: error.msg().to_string(),
+        })?;
+        // Where the event just read starts, xml-rs positions start at 0
+        let position = reader.position();
+        let location = Location::new(file, position.row as usize + 1, position.column as usize + 1);
+
+        match event {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attribute = |key: &str| {
+                    attributes
+                        .iter()
+                        .find(|attribute| attribute.name.local_name.eq_ignore_ascii_case(key))
+                        .map(|attribute| attribute.value.clone())
+                };
+                match name.local_name.as_str() {
+                    "LevelCollection" => {
+                        if let Some(author) = attribute("Copyright") {
+                            collection.author = Some(author);
+                        }
+                    }
+                    "Level" => {
+                        let title = attribute("Id")
+                            .unwrap_or_else(|| format!("Level {}", collection.levels.len() + 1));
+                        level = Some(LevelInfo {
+                            author: attribute("Copyright"),
+                            difficulty: attribute("Difficulty"),
+                            ..LevelInfo::new(title, Ok(LevelMap::default()))
+                        });
+                        rows.clear();
+                    }
+                    "L" => {
+                        // Rows start right after the first <L>, one per line
+                        if rows.is_empty() {
+                            rows_location = Location { column: location.column + 3, ..location };
+                        }
+                        rows.push(String::new());
+                    }
+                    _ => (),
+                }
+                elements.push(name.local_name);
+            }
+            XmlEvent::Characters(text) | XmlEvent::Whitespace(text) | XmlEvent::CData(text) => {
+                match (elements.last().map(String::as_str), level.as_mut()) {
+                    (Some("L"), _) => {
+                        if let Some(row) = rows.last_mut() {
+                            row.push_str(&text);
+                        }
+                    }
+                    (Some("Title"), None) => collection.title = text.trim().to_string(),
+                    (Some("Description"), None) => collection.description = Some(text.trim().to_string()),
+                    (Some("Comment"), Some(level)) => level.comment = Some(text.trim().to_string()),
+                    _ => (),
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                elements.pop();
+                if name.local_name == "Level" {
+                    if let Some(mut level) = level.take() {
+                        level.map = map::parse_xsb(&rows.join("\n"), rows_location.clone());
+                        collection.levels.push(level);
+                    }
+                }
+            }
+            XmlEvent::EndDocument => break,
+            _ => (),
+        }
+    }
+
+    Ok(collection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOK: &str = "Title: Test Pack
+Author: Someone
+
+; One Push
+#####
+#@$.#
+#####
+Author: First
+
+  ####
+###  #
+#@$*.#
+######
+Title: Two Boxes
+Comment:
+Both boxes
+on one row
+Comment-End:
+";
+
+    const SLC: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<SokobanLevels>
+  <Title>Test Pack</Title>
+  <Description>Two small levels</Description>
+  <LevelCollection Copyright="Someone">
+    <Level Id="One Push" Copyright="First">
+      <L>#####</L>
+      <L>#@$.#</L>
+      <L>#####</L>
+    </Level>
+    <Level Id="Two Boxes">
+      <L>  ####</L>
+      <L>###  #</L>
+      <L>#@$*.#</L>
+      <L>######</L>
+    </Level>
+  </LevelCollection>
+</SokobanLevels>
+"#;
+
+    #[test]
+    fn sok_collection() {
+        let collection = parse_sok(SOK, "test.sok");
+        assert_eq!(collection.title, "Test Pack");
+        assert_eq!(collection.author.as_deref(), Some("Someone"));
+        assert_eq!(collection.levels.len(), 2);
+
+        let (first, second) = (&collection.levels[0], &collection.levels[1]);
+        assert_eq!(first.title, "One Push");
+        assert_eq!(first.author.as_deref(), Some("First"));
+        let first_map = first.map.as_ref().unwrap();
+        assert_eq!((first_map.width, first_map.height), (5, 3));
+        assert!(first_map.tile(1, 1).player);
+        assert_eq!(first_map.origin, Location::new("test.sok", 5, 1));
+
+        assert_eq!(second.title, "Two Boxes");
+        assert_eq!(second.comment.as_deref(), Some("Both boxes\non one row"));
+        let second_map = second.map.as_ref().unwrap();
+        assert_eq!((second_map.width, second_map.height), (6, 4));
+        assert!(second_map.tile(3, 2).box_colour.is_some());
+    }
+
+    #[test]
+    fn slc_collection() {
+        let collection = parse_slc(SLC, "test.slc").unwrap();
+        assert_eq!(collection.title, "Test Pack");
+        assert_eq!(collection.description.as_deref(), Some("Two small levels"));
+        assert_eq!(collection.author.as_deref(), Some("Someone"));
+        assert_eq!(collection.levels.len(), 2);
+
+        let (first, second) = (&collection.levels[0], &collection.levels[1]);
+        assert_eq!(first.title, "One Push");
+        assert_eq!(first.author.as_deref(), Some("First"));
+        let first_map = first.map.as_ref().unwrap();
+        assert_eq!((first_map.width, first_map.height), (5, 3));
+        assert!(first_map.tile(1, 1).player);
+        assert_eq!(first_map.origin, Location::new("test.slc", 7, 10));
+
+        assert_eq!(second.title, "Two Boxes");
+        assert_eq!(second.author, None);
+        let second_map = second.map.as_ref().unwrap();
+        assert_eq!((second_map.width, second_map.height), (6, 4));
+        assert!(second_map.tile(3, 2).box_colour.is_some());
+    }
+
+    #[test]
+    fn sok_keeps_broken_levels() {
+        let collection = parse_sok("#####\n#@$x#\n#####\n\n#####\n#@$.#\n#####\n", "test.sok");
+        assert_eq!(collection.levels.len(), 2);
+        assert_eq!(collection.levels[0].title, "Level 1");
+        assert_eq!(
+            collection.levels[0].map.as_ref().err(),
+            Some(&LevelError::UnrecognizedItem { location: Location::new("test.sok", 2, 4), item: "x".to_string() })
+        );
+        assert!(collection.levels[1].map.is_ok());
+    }
+
+    #[test]
+    fn slc_invalid_xml() {
+        let error = parse_slc("<SokobanLevels>\n<Title>Test</Tilte>\n</SokobanLevels>", "test.slc").err();
+        assert!(matches!(error, Some(LevelError::InvalidXml { location, .. }) if location.line == 2));
+    }
+}
@@ -0,0 +1,76 @@
+use std::fs;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+pub const COLOURS_FILE: &str = "./resources/colours.toml";
+
+// The colours that ship with the game, for when the file cannot be read
+const BUILT_IN_COLOURS: &str = include_str!("../resources/colours.toml");
+
+// A box colour as defined in the colours file
+#[derive(Clone, Debug, Deserialize)]
+pub struct ColourInfo {
+    pub name: String,
+    // Written before B or S in level files
+    pub code: String,
+    #[serde(rename = "box")]
+    pub box_images: Vec<String>,
+    #[serde(rename = "spot")]
+    pub spot_image: String,
+    // Boxes fit every spot and spots take every box
+    #[serde(default)]
+    pub any: bool,
+}
+
+#[derive(Deserialize)]
+struct ColoursFile {
+    colours: Vec<ColourInfo>,
+}
+
+// The colours, with what was wrong with the file for the front end to report
+static PALETTE: OnceLock<(Vec<ColourInfo>, Vec<String>)> = OnceLock::new();
+
+// Reads the colours, leaving out the ones whose name or code is taken and
+// noting why
+fn parse_colours(source: &str, warnings: &mut Vec<String>) -> Result<Vec<ColourInfo>, String> {
+    let file = toml::from_str::<ColoursFile>(source).map_err(|error| error.to_string())?;
+    let mut colours: Vec<ColourInfo> = Vec::new();
+    for colour in file.colours {
+        if colour.code.is_empty() || colour.box_images.is_empty() {
+            warnings.push(format!("Colour {} needs a code and a box image", colour.name));
+        } else if colours.iter().any(|known| known.name == colour.name || known.code == colour.code) {
+            warnings.push(format!("Colour {} or its code {} is defined twice", colour.name, colour.code));
+        } else if colours.len() > u8::MAX as usize {
+            warnings.push(format!("Too many colours, {} is left out", colour.name));
+        } else {
+            colours.push(colour);
+        }
+    }
+    if colours.is_empty() {
+        return Err("no colours defined".to_string());
+    }
+    Ok(colours)
+}
+
+fn load_palette() -> &'static (Vec<ColourInfo>, Vec<String>) {
+    PALETTE.get_or_init(|| {
+        let source = fs::read_to_string(COLOURS_FILE).unwrap_or_else(|_| BUILT_IN_COLOURS.to_string());
+        let mut warnings = Vec::new();
+        let colours = parse_colours(&source, &mut warnings).unwrap_or_else(|error| {
+            warnings.push(format!("{}: {}", COLOURS_FILE, error));
+            parse_colours(BUILT_IN_COLOURS, &mut Vec::new()).expect("expected built-in colours")
+        });
+        (colours, warnings)
+    })
+}
+
+// Every box colour, loaded the first time it is needed
+pub fn palette() -> &'static [ColourInfo] {
+    &load_palette().0
+}
+
+// What was left out of the colours file
+pub fn warnings() -> &'static [String] {
+    &load_palette().1
+}
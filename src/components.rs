@@ -1,26 +1,151 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use specs::{Component, NullStorage, VecStorage, World, WorldExt};
 use std::fmt::Display;
 use std::fmt;
+use std::time::Duration;
 
-#[derive(PartialEq)]
-pub enum BoxColour {
-    Red,
-    Blue,
+use crate::colours::{self, ColourInfo};
+
+// One of the colours of the colours file, saved by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoxColour(u8);
+
+impl BoxColour {
+    pub fn all() -> impl Iterator<Item = BoxColour> {
+        (0..colours::palette().len()).map(|index| BoxColour(index as u8))
+    }
+
+    // XSB levels have no colours, all their boxes and spots get this one
+    pub fn first() -> Self {
+        BoxColour(0)
+    }
+
+    pub fn named(name: &str) -> Option<Self> {
+        Self::all().find(|colour| colour.info().name == name)
+    }
+
+    // Colour written as the given code in level files
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::all().find(|colour| colour.info().code == code)
+    }
+
+    pub fn info(self) -> &'static ColourInfo {
+        &colours::palette()[self.0 as usize]
+    }
+
+    pub fn is_any(self) -> bool {
+        self.info().any
+    }
+
+    // Whether a box of this colour counts on a spot of the given one
+    pub fn fits(self, spot: BoxColour) -> bool {
+        self == spot || self.is_any() || spot.is_any()
+    }
 }
 
-// #[derive(PartialEq)]
-pub enum RenderableKind {
-    Static,
-    Animated,
+impl Serialize for BoxColour {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.info().name)
+    }
+}
+
+impl<'de> Deserialize<'de> for BoxColour {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::named(&name).ok_or_else(|| de::Error::custom(format!("unknown box colour {}", name)))
+    }
+}
+
+// What a tile is, the skin decides how it looks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TileRole {
+    Wall,
+    Floor,
+    Player,
+    Box(BoxColour),
+    Spot(BoxColour),
+}
+
+impl TileRole {
+    // Name of the role in skin manifests: wall, floor, player, box_red, spot_red...
+    pub fn key(self) -> String {
+        match self {
+            TileRole::Wall => "wall".to_string(),
+            TileRole::Floor => "floor".to_string(),
+            TileRole::Player => "player".to_string(),
+            TileRole::Box(colour) => format!("box_{}", colour),
+            TileRole::Spot(colour) => format!("spot_{}", colour),
+        }
+    }
 }
 
 impl Display for BoxColour {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.info().name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Left,
+    Up,
+    Right,
+    Down,
+}
+
+impl Direction {
+    pub const ALL: [Direction; 4] = [Direction::Left, Direction::Up, Direction::Right, Direction::Down];
+
+    // Change of x and y when stepping in this direction
+    pub fn offset(self) -> (i8, i8) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Up => (0, -1),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, 1),
+        }
+    }
+
+    pub fn opposite(self) -> Self {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Up => Direction::Down,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+        }
+    }
+
+    // LURD notation: lowercase for a move, uppercase for a push
+    pub fn to_lurd(self, push: bool) -> char {
+        let c = match self {
+            Direction::Left => 'l',
+            Direction::Up => 'u',
+            Direction::Right => 'r',
+            Direction::Down => 'd',
+        };
+        if push { c.to_ascii_uppercase() } else { c }
+    }
+
+    pub fn from_lurd(c: char) -> Option<(Self, bool)> {
+        let direction = match c.to_ascii_lowercase() {
+            'l' => Direction::Left,
+            'u' => Direction::Up,
+            'r' => Direction::Right,
+            'd' => Direction::Down,
+            _ => return None,
+        };
+        Some((direction, c.is_ascii_uppercase()))
+    }
+}
+
+impl Display for Direction {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str(match self {
-            BoxColour::Red => "red",
-            BoxColour::Blue => "blue",
-        })?;
-        Ok(())
+            Direction::Left => "Left",
+            Direction::Up => "Up",
+            Direction::Right => "Right",
+            Direction::Down => "Down",
+        })
     }
 }
 
@@ -35,7 +160,7 @@ pub struct Position {
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Renderable {
-    pub paths: Vec<String>,
+    pub role: TileRole,
 }
 
 #[derive(Component, Default)]
@@ -46,6 +171,21 @@ pub struct Movable;
 #[storage(NullStorage)]
 pub struct Immovable;
 
+// Entity on its way from the given tile to its position, see the animation module
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(VecStorage)]
+pub struct Motion {
+    pub from: (u8, u8),
+    pub started_at: Duration,
+    // Tiles moved so far, the sprite frames follow them
+    pub steps: usize,
+}
+
+// Marks boxes that can no longer reach a spot of their colour
+#[derive(Component, Default)]
+#[storage(NullStorage)]
+pub struct Deadlocked;
+
 #[derive(Component)]
 #[storage(VecStorage)]
 pub struct Wall {}
@@ -66,31 +206,6 @@ pub struct BoxSpot {
     pub colour: BoxColour,
 }
 
-impl Renderable {
-    pub fn new_static(path: String) -> Self {
-        Self { paths: vec![path] }
-    }
-
-    pub fn new_animated(paths: Vec<String>) -> Self {
-        Self { paths }
-    }
-
-    pub fn kind(&self) -> RenderableKind {
-        match self.paths.len() {
-            0 => panic!("invalid renderable"),
-            1 => RenderableKind::Static,
-            _ => RenderableKind::Animated,
-        }
-    }
-
-    pub fn path(&self, path_index: usize) -> String {
-        // If we get asked for a path that is larger than the
-        // number of paths we actually have, we simply mod the index
-        // with the length to get an index that is in range.
-        self.paths[path_index % self.paths.len()].clone()
-    }
-}
-
 // Register components with the world
 pub fn register_components(world: &mut World) {
     world.register::<Position>();
@@ -101,4 +216,6 @@ pub fn register_components(world: &mut World) {
     world.register::<BoxSpot>();
     world.register::<Movable>();
     world.register::<Immovable>();
+    world.register::<Deadlocked>();
+    world.register::<Motion>();
 }
\ No newline at end of file
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::animation::Easing;
+use crate::camera::CameraMode;
+use crate::errors::SaveError;
+
+pub const CONFIG_FILE: &str = "./config.toml";
+
+// Settings the player can change by hand, every section may be left out
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // Directory under resources/skins of the skin to draw with
+    pub skin: String,
+    // hjkl move as in vi, the hint is then only on / and F1
+    pub vi_keys: bool,
+    // Action name to the names of its keys, see the bindings module
+    pub keys: BTreeMap<String, Vec<String>>,
+    // The same for the level editor
+    pub editor_keys: BTreeMap<String, Vec<String>>,
+    pub gamepad: GamepadConfig,
+    pub animation: AnimationConfig,
+    pub camera: CameraConfig,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    // follow, pan or fit
+    pub mode: CameraMode,
+    // Scale when following or panning
+    pub zoom: f32,
+    // Keep scales of 1 and up whole, so pixels stay square
+    pub integer_scaling: bool,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self { mode: CameraMode::Fit, zoom: 1.0, integer_scaling: true }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationConfig {
+    // Time a move takes from one tile to the next, 0 for none
+    pub duration_ms: u64,
+    // linear, ease_in, ease_out or ease_in_out
+    pub easing: Easing,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self { duration_ms: 120, easing: Easing::EaseOut }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    // How far the stick has to be pushed to move, from 0 to 1
+    pub deadzone: f32,
+    // A held direction moves again after the delay, then every interval
+    pub repeat_delay_ms: u64,
+    pub repeat_interval_ms: u64,
+    // Action name to the names of its buttons, like the keys
+    pub buttons: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for GamepadConfig {
+    fn default() -> Self {
+        Self { deadzone: 0.5, repeat_delay_ms: 300, repeat_interval_ms: 150, buttons: BTreeMap::new() }
+    }
+}
+
+// A missing config file means the defaults
+pub fn load_config(file: &str) -> Result<Config, SaveError> {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(_) => return Ok(Config::default()),
+    };
+    toml::from_str::<Config>(&source).map_err(|error| SaveError::Format {
+        file: file.to_string(),
+        message: error.to_string(),
+    })
+}
+
+pub fn save_config(config: &Config, file: &str) -> Result<(), SaveError> {
+    let source = toml::to_string(config).map_err(|error| SaveError::Format {
+        file: file.to_string(),
+        message: error.to_string(),
+    })?;
+    fs::write(file, source).map_err(|error| SaveError::Io { file: file.to_string(), message: error.to_string() })
+}
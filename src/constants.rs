@@ -1,11 +1,44 @@
+use std::time::Duration;
+
 pub const TILE_SIZE: f32 = 32.0;
-pub const MAP_WIDTH: u8 = 8;
-pub const MAP_HEIGHT: u8 = 9;
 
 pub const STATE_DLMR_WIDTH: u8 = 1;
 pub const STATE_DLMR_HEIGHT: u8 = 1;
 
 pub const STATE_WIDTH: u8 = 5;
-pub const STATE_HEIGHT: u8 = 7;
+pub const STATE_HEIGHT: u8 = 9;
+
+// Largest board the window grows to, in tiles, the camera shows the rest
+pub const MAX_BOARD_WIDTH: u8 = 24;
+pub const MAX_BOARD_HEIGHT: u8 = 18;
+
+// Smallest window the player can size it to
+pub const MIN_WINDOW_WIDTH: f32 = 320.0;
+pub const MIN_WINDOW_HEIGHT: f32 = 320.0;
+
+// How far the camera zooms out and in
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 8.0;
+
+// Level of the default collection the game starts with
+pub const FIRST_LEVEL: usize = 1;
+
+// How long the solver may think about a hint before giving up
+pub const HINT_TIME_BUDGET: Duration = Duration::from_secs(3);
+
+// How long the won level stays on screen before its summary opens
+pub const SUMMARY_DELAY: Duration = Duration::from_secs(2);
+
+// How long after the win the summary closes by itself and the next level starts
+pub const LEVEL_ADVANCE_DELAY: Duration = Duration::from_secs(6);
+
+// Replay playback speed, in steps per second, and how far it can be changed
+pub const REPLAY_STEPS_PER_SECOND: f32 = 4.0;
+pub const REPLAY_MIN_STEPS_PER_SECOND: f32 = 0.5;
+pub const REPLAY_MAX_STEPS_PER_SECOND: f32 = 64.0;
+
+// Actions kept while a move is animated, the ones pressed after them are dropped
+pub const INPUT_BUFFER: usize = 2;
 
-pub const FIRST_LEVEL:u8 = 1;
\ No newline at end of file
+// Time between the steps of a path walked after a click
+pub const ROUTE_STEP_INTERVAL: Duration = Duration::from_millis(100);
@@ -0,0 +1,285 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::components::{BoxColour, Direction};
+use crate::map::LevelMap;
+use crate::solver;
+
+// Positions the corral test looks at before giving up on finding a deadlock,
+// it runs after every push so it has to stay small
+const CORRAL_NODE_LIMIT: usize = 200;
+
+// Cells of the level a box can never leave for a spot of its colour,
+// worked out once per level
+#[derive(Default)]
+pub struct DeadSquares {
+    pub cells: HashMap<BoxColour, Vec<bool>>,
+}
+
+impl DeadSquares {
+    pub fn new(level_map: &LevelMap) -> Self {
+        let mut cells = HashMap::new();
+        for tile in level_map.tiles.iter() {
+            if let Some(colour) = tile.box_colour.or(tile.spot_colour) {
+                cells
+                    .entry(colour)
+                    .or_insert_with(|| solver::dead_squares(level_map, colour));
+            }
+        }
+        Self { cells }
+    }
+
+    fn is_dead(&self, level_map: &LevelMap, cell: (u8, u8), colour: BoxColour) -> bool {
+        let index = cell.1 as usize * level_map.width as usize + cell.0 as usize;
+        self.cells
+            .get(&colour)
+            .and_then(|cells| cells.get(index).copied())
+            .unwrap_or(true)
+    }
+}
+
+// Neighbour of the cell, None when it would be outside of the map
+fn neighbour(level_map: &LevelMap, cell: (u8, u8), dx: i16, dy: i16) -> Option<(u8, u8)> {
+    let x = cell.0 as i16 + dx;
+    let y = cell.1 as i16 + dy;
+    if x < 0 || y < 0 || x >= level_map.width as i16 || y >= level_map.height as i16 {
+        return None;
+    }
+    Some((x as u8, y as u8))
+}
+
+fn is_solid(level_map: &LevelMap, cell: Option<(u8, u8)>) -> bool {
+    cell.is_none_or(|(x, y)| level_map.tile(x, y).wall || !level_map.tile(x, y).floor)
+}
+
+fn is_on_own_spot(level_map: &LevelMap, (x, y): (u8, u8)) -> bool {
+    let tile = level_map.tile(x, y);
+    match (tile.box_colour, tile.spot_colour) {
+        (Some(colour), Some(spot)) => colour.fits(spot),
+        _ => false,
+    }
+}
+
+// A box is frozen when it can move along neither axis, now or ever: boxes
+// already being looked at count as walls so that blocks of boxes are caught
+fn is_frozen(level_map: &LevelMap, dead: &DeadSquares, cell: (u8, u8), checked: &mut Vec<(u8, u8)>) -> bool {
+    checked.push(cell);
+    let frozen = is_blocked(level_map, dead, cell, (1, 0), checked)
+        && is_blocked(level_map, dead, cell, (0, 1), checked);
+    checked.pop();
+    frozen
+}
+
+fn is_blocked(
+    level_map: &LevelMap,
+    dead: &DeadSquares,
+    cell: (u8, u8),
+    (dx, dy): (i16, i16),
+    checked: &mut Vec<(u8, u8)>,
+) -> bool {
+    let sides = [neighbour(level_map, cell, -dx, -dy), neighbour(level_map, cell, dx, dy)];
+    if sides
+        .iter()
+        .any(|side| is_solid(level_map, *side) || side.is_some_and(|side| checked.contains(&side)))
+    {
+        return true;
+    }
+
+    let colour = match level_map.tile(cell.0, cell.1).box_colour {
+        Some(colour) => colour,
+        None => return false,
+    };
+    if sides.iter().flatten().all(|side| dead.is_dead(level_map, *side, colour)) {
+        return true;
+    }
+
+    sides.iter().flatten().any(|side| {
+        level_map.tile(side.0, side.1).box_colour.is_some() && is_frozen(level_map, dead, *side, checked)
+    })
+}
+
+fn is_free(level_map: &LevelMap, cell: (u8, u8), boxes: &[((u8, u8), BoxColour)]) -> bool {
+    !is_solid(level_map, Some(cell)) && boxes.iter().all(|(box_cell, _)| *box_cell != cell)
+}
+
+// Cells the player can walk to from the given cell, row by row
+fn reachable_cells(level_map: &LevelMap, player: (u8, u8), boxes: &[((u8, u8), BoxColour)]) -> Vec<bool> {
+    let mut reachable = vec![false; level_map.tiles.len()];
+    let mut stack = vec![player];
+    while let Some((x, y)) = stack.pop() {
+        let index = y as usize * level_map.width as usize + x as usize;
+        if reachable[index] {
+            continue;
+        }
+        reachable[index] = true;
+        stack.extend(level_map.neighbours(x, y).into_iter().filter(|cell| is_free(level_map, *cell, boxes)));
+    }
+    reachable
+}
+
+// Boxes next to a free cell the player cannot get to, with the cells the
+// player can get to
+fn corral_boxes(level_map: &LevelMap, boxes: &[((u8, u8), BoxColour)], player: (u8, u8)) -> (Vec<(u8, u8)>, Vec<bool>) {
+    let reachable = reachable_cells(level_map, player, boxes);
+    let corral = boxes
+        .iter()
+        .map(|(cell, _)| *cell)
+        .filter(|(x, y)| {
+            level_map.neighbours(*x, *y).into_iter().any(|(nx, ny)| {
+                is_free(level_map, (nx, ny), boxes) && !reachable[ny as usize * level_map.width as usize + nx as usize]
+            })
+        })
+        .collect();
+    (corral, reachable)
+}
+
+// Pushes only the corral boxes, every other box taken away, looking for a way
+// to either get them all onto their spots or let the player into the cells
+// they fence off. The corral is deadlocked when there is none; when the
+// search runs out of positions it is taken not to be.
+fn is_corral_deadlocked(
+    level_map: &LevelMap,
+    dead: &DeadSquares,
+    corral: Vec<((u8, u8), BoxColour)>,
+    player: (u8, u8),
+    fenced: &[bool],
+) -> bool {
+    let width = level_map.width as usize;
+    let index = |(x, y): (u8, u8)| y as usize * width + x as usize;
+
+    let mut seen = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((corral, player));
+    while let Some((boxes, player)) = queue.pop_front() {
+        let reachable = reachable_cells(level_map, player, &boxes);
+        // Same boxes with the player anywhere in the same area is the same position
+        let area = reachable.iter().position(|reachable| *reachable).unwrap_or(0);
+        let mut key = boxes.iter().map(|(cell, colour)| (index(*cell), *colour)).collect::<Vec<_>>();
+        key.sort_by_key(|(cell, _)| *cell);
+        if !seen.insert((key, area)) {
+            continue;
+        }
+        let opened = reachable.iter().zip(fenced).any(|(reachable, fenced)| *reachable && *fenced);
+        if opened || seen.len() > CORRAL_NODE_LIMIT {
+            return false;
+        }
+        let placed = boxes.iter().all(|((x, y), colour)| {
+            level_map.tile(*x, *y).spot_colour.is_some_and(|spot| colour.fits(spot))
+        });
+        if placed {
+            return false;
+        }
+
+        for (box_index, (cell, colour)) in boxes.iter().enumerate() {
+            for direction in Direction::ALL.iter() {
+                let (dx, dy) = direction.offset();
+                let from = neighbour(level_map, *cell, -dx as i16, -dy as i16);
+                let to = neighbour(level_map, *cell, dx as i16, dy as i16);
+                let (from, to) = match (from, to) {
+                    (Some(from), Some(to)) => (from, to),
+                    _ => continue,
+                };
+                if !reachable[index(from)] || !is_free(level_map, to, &boxes) || dead.is_dead(level_map, to, *colour) {
+                    continue;
+                }
+                let mut pushed = boxes.clone();
+                pushed[box_index].0 = to;
+                queue.push_back((pushed, *cell));
+            }
+        }
+    }
+    true
+}
+
+// Returns the boxes that make the level unwinnable: boxes on dead squares,
+// frozen boxes off their spots, or boxes fencing off part of the level that
+// could not be solved even with every other box taken away
+pub fn find_deadlocked_boxes(level_map: &LevelMap, dead: &DeadSquares) -> Vec<(u8, u8)> {
+    let boxes = (0..level_map.height)
+        .flat_map(|y| (0..level_map.width).map(move |x| (x, y)))
+        .filter_map(|(x, y)| level_map.tile(x, y).box_colour.map(|colour| ((x, y), colour)))
+        .collect::<Vec<_>>();
+
+    let stuck = boxes
+        .iter()
+        .map(|(cell, _)| *cell)
+        .filter(|cell| !is_on_own_spot(level_map, *cell))
+        .filter(|cell| {
+            let colour = level_map.tile(cell.0, cell.1).box_colour;
+            colour.is_some_and(|colour| dead.is_dead(level_map, *cell, colour))
+                || is_frozen(level_map, dead, *cell, &mut Vec::new())
+        })
+        .collect::<Vec<_>>();
+    if !stuck.is_empty() {
+        return stuck;
+    }
+
+    let player = (0..level_map.height)
+        .flat_map(|y| (0..level_map.width).map(move |x| (x, y)))
+        .find(|(x, y)| level_map.tile(*x, *y).player);
+    let player = match player {
+        Some(player) => player,
+        None => return Vec::new(),
+    };
+    let (corral, reachable) = corral_boxes(level_map, &boxes, player);
+    if corral.iter().all(|cell| is_on_own_spot(level_map, *cell)) {
+        return Vec::new();
+    }
+
+    // Free cells the corral boxes keep the player out of
+    let fenced = (0..level_map.height)
+        .flat_map(|y| (0..level_map.width).map(move |x| (x, y)))
+        .zip(reachable.iter())
+        .map(|(cell, reachable)| is_free(level_map, cell, &boxes) && !reachable)
+        .collect::<Vec<_>>();
+    let corral_boxes = boxes.iter().copied().filter(|(cell, _)| corral.contains(cell)).collect();
+    if is_corral_deadlocked(level_map, dead, corral_boxes, player, &fenced) {
+        corral
+    } else {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::test_level;
+
+    #[test]
+    fn movable_boxes() {
+        let level_map = test_level("#######\n#     #\n# $ $ #\n#@.  .#\n#######");
+        assert!(find_deadlocked_boxes(&level_map, &DeadSquares::new(&level_map)).is_empty());
+    }
+
+    #[test]
+    fn dead_square() {
+        let level_map = test_level("#####\n#@ .#\n#$  #\n#####");
+        assert_eq!(find_deadlocked_boxes(&level_map, &DeadSquares::new(&level_map)), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn frozen_pair() {
+        // Side by side against the wall, neither can be pushed along it
+        let level_map = test_level("#######\n#     #\n# @   #\n#.$$ .#\n#######");
+        assert_eq!(find_deadlocked_boxes(&level_map, &DeadSquares::new(&level_map)), vec![(2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn frozen_on_spots() {
+        let level_map = test_level("######\n#    #\n# @  #\n# ** #\n######");
+        assert!(find_deadlocked_boxes(&level_map, &DeadSquares::new(&level_map)).is_empty());
+    }
+
+    #[test]
+    fn corral() {
+        // The top left box could only be pushed right from the cell it fences off
+        let level_map = test_level("#######\n# $.  #\n##  . #\n#   $ #\n#   @##\n#######");
+        assert_eq!(find_deadlocked_boxes(&level_map, &DeadSquares::new(&level_map)), vec![(2, 1)]);
+    }
+
+    #[test]
+    fn corral_the_player_can_open() {
+        // Pushing the box through the doorway lets the player into the room
+        let level_map = test_level("#######\n#  #  #\n#@ $ .#\n#  #  #\n#######");
+        assert!(find_deadlocked_boxes(&level_map, &DeadSquares::new(&level_map)).is_empty());
+    }
+}
@@ -0,0 +1,576 @@
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use specs::{World, WorldExt};
+
+use crate::actions::Action;
+use crate::camera::Camera;
+use crate::collection::{self, Collection, LevelInfo, DEFAULT_COLLECTION, LEVELS_DIRECTORY};
+use crate::components::{BoxColour, Direction};
+use crate::errors::{LevelError, Location};
+use crate::levels;
+use crate::map::{self, LevelMap, Tile};
+use crate::resources::{CurrentMap, EventQueue, Gameplay, InputQueue, LevelChange, LevelLoadError, LevelQueue, MapSize};
+use crate::screens::{Screen, Screens};
+use crate::skins::Skins;
+
+// Size of a new level, walls all around
+const NEW_LEVEL_WIDTH: u8 = 7;
+const NEW_LEVEL_HEIGHT: u8 = 7;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Brush {
+    // Outside of the level, nothing drawn
+    Empty,
+    Floor,
+    Wall,
+    Player,
+    Box(BoxColour),
+    Spot(BoxColour),
+}
+
+// Everything but boxes and spots, those follow for every colour
+const BASIC_BRUSHES: [Brush; 4] = [Brush::Empty, Brush::Floor, Brush::Wall, Brush::Player];
+
+pub fn brushes() -> Vec<Brush> {
+    BASIC_BRUSHES
+        .iter()
+        .copied()
+        .chain(BoxColour::all().flat_map(|colour| vec![Brush::Box(colour), Brush::Spot(colour)]))
+        .collect()
+}
+
+impl Display for Brush {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Brush::Empty => fmt.write_str("empty"),
+            Brush::Floor => fmt.write_str("floor"),
+            Brush::Wall => fmt.write_str("wall"),
+            Brush::Player => fmt.write_str("player"),
+            Brush::Box(colour) => write!(fmt, "{} box", colour),
+            Brush::Spot(colour) => write!(fmt, "{} spot", colour),
+        }
+    }
+}
+
+// The level being edited. The board entities are rebuilt from the map after
+// every change, so it is drawn like any other level.
+#[derive(Default)]
+pub struct Editor {
+    pub active: bool,
+    pub map: LevelMap,
+    pub cursor: (u8, u8),
+    pub brush: usize,
+    // Level file the map is written to, the extension gives the format
+    pub file: String,
+    // Outcome of the last check or save
+    pub message: String,
+    // Collection and level that were being played before the test run
+    pub stashed: Option<(Collection, usize)>,
+}
+
+impl Editor {
+    pub fn brush(&self) -> Brush {
+        let brushes = brushes();
+        brushes[self.brush % brushes.len()]
+    }
+}
+
+fn new_map(origin: Location) -> LevelMap {
+    let mut level_map = LevelMap::new(NEW_LEVEL_WIDTH, NEW_LEVEL_HEIGHT, origin);
+    for y in 0..level_map.height {
+        for x in 0..level_map.width {
+            let border = x == 0 || y == 0 || x + 1 == level_map.width || y + 1 == level_map.height;
+            let tile = level_map.tile_mut(x, y);
+            tile.floor = true;
+            tile.wall = border;
+        }
+    }
+    level_map
+}
+
+// Levels of the default collection are written back to their own file, in
+// its own format. Any other level becomes a new level_NN file.
+fn level_file(level_map: &LevelMap) -> String {
+    let path = Path::new(&level_map.origin.file);
+    let own_file = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .is_some_and(|stem| stem.strip_prefix("level_").is_some_and(|number| number.parse::<u32>().is_ok()))
+        && path.parent() == Some(Path::new(LEVELS_DIRECTORY))
+        && (path.extension().is_none() || is_xsb(&level_map.origin.file));
+    if own_file {
+        return path.display().to_string();
+    }
+    new_level_file(None)
+}
+
+// First level_NN number with no file of either format
+fn new_level_file(extension: Option<&str>) -> String {
+    (0..)
+        .map(|number| Path::new(LEVELS_DIRECTORY).join(format!("level_{:02}", number)))
+        .find(|path| !path.exists() && !path.with_extension("xsb").exists())
+        .map(|path| match extension {
+            Some(extension) => path.with_extension(extension),
+            None => path,
+        })
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn is_xsb(file: &str) -> bool {
+    Path::new(file).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("xsb"))
+}
+
+// Copy of the map with a row or column added (amount 1) or taken away
+// (amount -1) on the given side, None when it would get too small or large
+fn resized(level_map: &LevelMap, side: Direction, amount: i16) -> Option<LevelMap> {
+    let (left, top, right, bottom) = match side {
+        Direction::Left => (amount, 0, 0, 0),
+        Direction::Up => (0, amount, 0, 0),
+        Direction::Right => (0, 0, amount, 0),
+        Direction::Down => (0, 0, 0, amount),
+    };
+    let width = level_map.width as i16 + left + right;
+    let height = level_map.height as i16 + top + bottom;
+    if width < 3 || height < 3 || width > u8::MAX as i16 || height > u8::MAX as i16 {
+        return None;
+    }
+
+    let mut resized = LevelMap::new(width as u8, height as u8, level_map.origin.clone());
+    for y in 0..resized.height {
+        for x in 0..resized.width {
+            let (old_x, old_y) = (x as i16 - left, y as i16 - top);
+            if old_x >= 0 && old_y >= 0 && old_x < level_map.width as i16 && old_y < level_map.height as i16 {
+                *resized.tile_mut(x, y) = level_map.tile(old_x as u8, old_y as u8).clone();
+            }
+        }
+    }
+    Some(resized)
+}
+
+fn paint(level_map: &mut LevelMap, (x, y): (u8, u8), brush: Brush) {
+    // There is only ever one player
+    if brush == Brush::Player {
+        for tile in level_map.tiles.iter_mut() {
+            tile.player = false;
+        }
+    }
+
+    let tile = level_map.tile_mut(x, y);
+    match brush {
+        Brush::Empty => *tile = Tile::default(),
+        Brush::Floor => *tile = Tile { floor: true, ..Tile::default() },
+        Brush::Wall => *tile = Tile { floor: true, wall: true, ..Tile::default() },
+        Brush::Player => {
+            tile.floor = true;
+            tile.wall = false;
+            tile.player = true;
+            tile.box_colour = None;
+        }
+        Brush::Box(colour) => {
+            tile.floor = true;
+            tile.wall = false;
+            tile.player = false;
+            tile.box_colour = Some(colour);
+        }
+        Brush::Spot(colour) => {
+            tile.floor = true;
+            tile.wall = false;
+            tile.spot_colour = Some(colour);
+        }
+    }
+}
+
+// Replaces the board entities with the map being edited
+fn show_map(world: &mut World, level_map: &LevelMap) {
+    world.delete_all();
+    world.maintain();
+    world.write_resource::<EventQueue>().events.clear();
+    world.write_resource::<LevelLoadError>().error = None;
+    *world.write_resource::<MapSize>() = MapSize { width: level_map.width, height: level_map.height };
+    map::load_map(world, level_map);
+}
+
+// Saves the level to its file in the file's format. Exporting to XSB writes a
+// new level_NN.xsb unless the file already is one, and goes on editing that.
+fn write_level(editor: &mut Editor, export_xsb: bool) -> Result<String, LevelError> {
+    let file = if export_xsb && !is_xsb(&editor.file) { new_level_file(Some("xsb")) } else { editor.file.clone() };
+    let source = if is_xsb(&file) { map::write_xsb(&editor.map)? } else { map::write_map(&editor.map)? };
+    fs::write(&file, source).map_err(|error| LevelError::Io { file: file.clone(), message: error.to_string() })?;
+    editor.map.origin = Location::new(&file, 1, 1);
+    editor.file = file.clone();
+    Ok(file)
+}
+
+// Starts the edited level on its own, going back to the editor afterwards
+fn test_play(world: &mut World) {
+    let level_map = world.read_resource::<Editor>().map.clone();
+    if let Err(error) = level_map.validate() {
+        world.write_resource::<Editor>().message = error.to_string();
+        return;
+    }
+
+    let level = world.read_resource::<Gameplay>().level;
+    let played = world.remove::<Collection>().unwrap_or_default();
+    world.write_resource::<Editor>().stashed = Some((played, level));
+
+    // Levels tried from the editor belong to no collection and are not scored
+    let collection = Collection {
+        title: "Level editor".to_string(),
+        levels: vec![LevelInfo::new("Edited level".to_string(), Ok(level_map))],
+        ..Collection::default()
+    };
+    levels::start_collection(world, collection, 0);
+    world.write_resource::<Screens>().push(Screen::Playing);
+}
+
+// Runs the editor while its screen is active: applies the queued actions to the
+// map and shows the result
+pub fn update_editor(world: &mut World) {
+    if world.read_resource::<Screens>().current() != Screen::Editor {
+        return;
+    }
+
+    // Back from a test run, the played collection comes back
+    let stashed = world.write_resource::<Editor>().stashed.take();
+    if let Some((collection, level)) = stashed {
+        world.insert(collection);
+        world.write_resource::<Gameplay>().level = level;
+        let level_map = world.read_resource::<Editor>().map.clone();
+        show_map(world, &level_map);
+    }
+
+    // Start on the level that was played, or a new one when there is none
+    if !world.read_resource::<Editor>().active {
+        let level_map = match world.read_resource::<LevelLoadError>().error {
+            None => world.read_resource::<CurrentMap>().map.clone(),
+            Some(_) => new_map(Location::default()),
+        };
+        let file = level_file(&level_map);
+        let level_map = LevelMap { origin: Location::new(&file, 1, 1), ..level_map };
+        *world.write_resource::<Editor>() = Editor {
+            active: true,
+            map: level_map.clone(),
+            file,
+            ..Editor::default()
+        };
+        show_map(world, &level_map);
+    }
+
+    let action = match world.write_resource::<InputQueue>().pop_front() {
+        Some(action) => action,
+        None => return,
+    };
+
+    if world.write_resource::<Camera>().apply(action) || world.write_resource::<Skins>().apply(action) {
+        return;
+    }
+
+    let mut editor = world.write_resource::<Editor>();
+    let mut changed = true;
+    match action {
+        Action::Move(direction) => {
+            let (dx, dy) = direction.offset();
+            let x = (editor.cursor.0 as i16 + dx as i16).clamp(0, editor.map.width as i16 - 1);
+            let y = (editor.cursor.1 as i16 + dy as i16).clamp(0, editor.map.height as i16 - 1);
+            editor.cursor = (x as u8, y as u8);
+            changed = false;
+        }
+        Action::Click { x, y } if x < editor.map.width && y < editor.map.height => {
+            editor.cursor = (x, y);
+            let (cursor, brush) = (editor.cursor, editor.brush());
+            paint(&mut editor.map, cursor, brush);
+        }
+        Action::Paint | Action::Confirm => {
+            let (cursor, brush) = (editor.cursor, editor.brush());
+            paint(&mut editor.map, cursor, brush);
+        }
+        Action::SelectBrush(brush) if brush < brushes().len() => {
+            editor.brush = brush;
+            changed = false;
+        }
+        Action::NextBrush => {
+            editor.brush = (editor.brush + 1) % brushes().len();
+            changed = false;
+        }
+        Action::Grow(side) | Action::Shrink(side) => {
+            let amount = if let Action::Grow(_) = action { 1 } else { -1 };
+            match resized(&editor.map, side, amount) {
+                Some(level_map) => {
+                    editor.map = level_map;
+                    // Keep the cursor on the same cell where there still is one
+                    let (x, y) = match side {
+                        Direction::Left => (editor.cursor.0 as i16 + amount, editor.cursor.1 as i16),
+                        Direction::Up => (editor.cursor.0 as i16, editor.cursor.1 as i16 + amount),
+                        _ => (editor.cursor.0 as i16, editor.cursor.1 as i16),
+                    };
+                    editor.cursor = (
+                        x.clamp(0, editor.map.width as i16 - 1) as u8,
+                        y.clamp(0, editor.map.height as i16 - 1) as u8,
+                    );
+                }
+                None => changed = false,
+            }
+        }
+        // A new level gets a file of its own, the one edited before is kept
+        Action::NewLevel => {
+            editor.file = new_level_file(None);
+            editor.map = new_map(Location::new(&editor.file, 1, 1));
+            editor.cursor = (0, 0);
+        }
+        Action::Validate => {
+            editor.message = match editor.map.validate() {
+                Ok(()) => "The level can be played".to_string(),
+                Err(error) => error.to_string(),
+            };
+            changed = false;
+        }
+        Action::SaveLevel | Action::SaveXsb => {
+            editor.message = match write_level(&mut editor, action == Action::SaveXsb) {
+                Ok(file) => format!("Saved to {}", file),
+                Err(error) => error.to_string(),
+            };
+
+            // The level list shows what was just written
+            drop(editor);
+            if world.read_resource::<Collection>().name == DEFAULT_COLLECTION {
+                if let Ok(collection) = collection::load_collection(DEFAULT_COLLECTION) {
+                    world.insert(collection);
+                }
+            }
+            return;
+        }
+        Action::TestPlay => {
+            drop(editor);
+            test_play(world);
+            return;
+        }
+        // Leave the editor, the level that was played comes back
+        Action::Back => {
+            editor.active = false;
+            drop(editor);
+            world.write_resource::<Screens>().pop();
+            world.write_resource::<LevelQueue>().change = Some(LevelChange::Restart);
+            return;
+        }
+        _ => changed = false,
+    }
+
+    if changed {
+        editor.message.clear();
+        let level_map = editor.map.clone();
+        drop(editor);
+        show_map(world, &level_map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use specs::{Component, Join};
+
+    use super::*;
+    use crate::components::{BoxSpot, Player, Position, Wall};
+    use crate::constants::{LEVEL_ADVANCE_DELAY, SUMMARY_DELAY};
+    use crate::resources::{GameplayState, Time};
+    use crate::simulation::{test_simulation, Simulation};
+
+    // A file of its own under the temporary directory, for each test
+    fn temp_file(name: &str, extension: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("sokoban-editor-{}-{}.{}", name, std::process::id(), extension))
+            .display()
+            .to_string()
+    }
+
+    // The editor opened on a one-push level
+    fn editing() -> Simulation {
+        let mut simulation = test_simulation("#####\n#@$.#\n#####");
+        simulation.world.write_resource::<Screens>().push(Screen::Editor);
+        simulation.update();
+        simulation
+    }
+
+    // Cells holding an entity with the given component
+    fn cells<T: Component>(world: &World) -> Vec<(u8, u8)> {
+        let mut cells = (&world.read_storage::<Position>(), &world.read_storage::<T>())
+            .join()
+            .map(|(position, _)| (position.x, position.y))
+            .collect::<Vec<_>>();
+        cells.sort();
+        cells
+    }
+
+    fn tiles(level_map: &LevelMap, has: impl Fn(&Tile) -> bool) -> Vec<(u8, u8)> {
+        let mut cells = (0..level_map.height)
+            .flat_map(|y| (0..level_map.width).map(move |x| (x, y)))
+            .filter(|(x, y)| has(level_map.tile(*x, *y)))
+            .collect::<Vec<_>>();
+        cells.sort();
+        cells
+    }
+
+    // Writes the edited level, then loads what was written onto the board
+    fn round_trip(extension: &str) {
+        let mut simulation = editing();
+        let file = temp_file("round-trip", extension);
+        let level_map = map::test_level("######\n#@$. #\n# $ .#\n######");
+        let mut editor = Editor { map: level_map.clone(), file: file.clone(), ..Editor::default() };
+        assert_eq!(write_level(&mut editor, false).unwrap(), file);
+        assert_eq!(editor.map.origin.file, file);
+
+        let source = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        let read_back = if is_xsb(&file) {
+            map::parse_xsb(&source, Location::new(&file, 1, 1))
+        } else {
+            map::parse_map(&source, Location::new(&file, 1, 1))
+        };
+        show_map(&mut simulation.world, &read_back.unwrap());
+
+        let world = &simulation.world;
+        assert_eq!(cells::<Wall>(world), tiles(&level_map, |tile| tile.wall));
+        assert_eq!(cells::<Player>(world), tiles(&level_map, |tile| tile.player));
+        assert_eq!(cells::<crate::components::Box>(world), tiles(&level_map, |tile| tile.box_colour.is_some()));
+        assert_eq!(cells::<BoxSpot>(world), tiles(&level_map, |tile| tile.spot_colour.is_some()));
+    }
+
+    #[test]
+    fn levels_of_the_default_collection_keep_their_file() {
+        for file in ["./resources/levels/level_01", "./resources/levels/level_02.xsb"] {
+            let level_map = LevelMap::new(3, 3, Location::new(file, 1, 1));
+            assert_eq!(level_file(&level_map), file);
+        }
+    }
+
+    #[test]
+    fn other_levels_get_a_new_file() {
+        for file in ["test.xsb", "./resources/levels/tutorial.sok", "./resources/levels/level_01.txt"] {
+            let level_map = LevelMap::new(3, 3, Location::new(file, 1, 1));
+            let new_file = level_file(&level_map);
+            assert_ne!(new_file, file);
+            assert!(!Path::new(&new_file).exists());
+            assert_eq!(Path::new(&new_file).parent(), Some(Path::new(LEVELS_DIRECTORY)));
+        }
+    }
+
+    #[test]
+    fn native_level_is_written_and_read_back() {
+        round_trip("txt");
+    }
+
+    #[test]
+    fn xsb_level_is_written_and_read_back() {
+        round_trip("xsb");
+    }
+
+    #[test]
+    fn resizing_keeps_the_cursor_on_the_map() {
+        let mut simulation = editing();
+        simulation.world.write_resource::<Editor>().cursor = (4, 2);
+
+        simulation.step(Action::Shrink(Direction::Right));
+        let editor = simulation.world.read_resource::<Editor>();
+        assert_eq!((editor.map.width, editor.map.height, editor.cursor), (4, 3, (3, 2)));
+        drop(editor);
+
+        // Growing on the left moves the cursor along with its cell
+        simulation.step(Action::Grow(Direction::Left));
+        let editor = simulation.world.read_resource::<Editor>();
+        assert_eq!((editor.map.width, editor.cursor), (5, (4, 2)));
+        drop(editor);
+
+        // Three rows is as small as a level gets
+        simulation.step(Action::Shrink(Direction::Up));
+        let editor = simulation.world.read_resource::<Editor>();
+        assert_eq!((editor.map.height, editor.cursor), (3, (4, 2)));
+    }
+
+    #[test]
+    fn every_brush_paints_its_tile() {
+        for brush in brushes() {
+            let mut level_map = new_map(Location::default());
+            level_map.tile_mut(1, 1).player = true;
+            level_map.tile_mut(2, 2).box_colour = Some(BoxColour::first());
+            paint(&mut level_map, (2, 2), brush);
+
+            let tile = level_map.tile(2, 2);
+            match brush {
+                Brush::Empty => assert!(!tile.floor && !tile.wall && tile.box_colour.is_none()),
+                Brush::Floor => assert!(tile.floor && !tile.wall && tile.box_colour.is_none()),
+                Brush::Wall => assert!(tile.wall && tile.box_colour.is_none()),
+                Brush::Player => {
+                    assert!(tile.player && tile.box_colour.is_none());
+                    assert!(!level_map.tile(1, 1).player);
+                }
+                Brush::Box(colour) => assert_eq!((tile.box_colour, tile.player), (Some(colour), false)),
+                Brush::Spot(colour) => assert_eq!(tile.spot_colour, Some(colour)),
+            }
+            if brush != Brush::Player {
+                assert!(level_map.tile(1, 1).player);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_level_is_not_test_played() {
+        let mut simulation = editing();
+        paint(&mut simulation.world.write_resource::<Editor>().map, (1, 1), Brush::Floor);
+
+        simulation.step(Action::TestPlay);
+        let editor = simulation.world.read_resource::<Editor>();
+        assert!(!editor.message.is_empty());
+        assert!(editor.stashed.is_none());
+        assert_eq!(simulation.world.read_resource::<Screens>().current(), Screen::Editor);
+        assert_ne!(simulation.world.read_resource::<Collection>().title, "Level editor");
+    }
+
+    #[test]
+    fn won_test_play_goes_back_to_the_editor() {
+        for leave in [None, Some(Action::Confirm), Some(Action::Back)] {
+            let mut simulation = editing();
+            simulation.step(Action::TestPlay);
+            simulation.step(Action::Move(Direction::Right));
+            assert_eq!(simulation.state(), GameplayState::Won);
+
+            simulation.world.write_resource::<Time>().delta += SUMMARY_DELAY;
+            simulation.update();
+            assert_eq!(simulation.world.read_resource::<Screens>().current(), Screen::WonSummary);
+            match leave {
+                Some(action) => simulation.step(action),
+                None => {
+                    simulation.world.write_resource::<Time>().delta += LEVEL_ADVANCE_DELAY - SUMMARY_DELAY;
+                    simulation.update()
+                }
+            };
+
+            assert_eq!(simulation.world.read_resource::<Screens>().current(), Screen::Editor);
+            assert_ne!(simulation.world.read_resource::<Collection>().title, "Level editor");
+            assert!(simulation.world.read_resource::<Editor>().stashed.is_none());
+            assert_eq!(cells::<Player>(&simulation.world), vec![(1, 1)]);
+        }
+    }
+
+    #[test]
+    fn new_level_keeps_the_edited_file() {
+        let mut simulation = editing();
+        let original = temp_file("original", "xsb");
+        fs::write(&original, "#####\n#@$.#\n#####\n").unwrap();
+        simulation.world.write_resource::<Editor>().file = original.clone();
+
+        simulation.step(Action::NewLevel);
+        simulation.step(Action::SaveLevel);
+        let file = simulation.world.read_resource::<Editor>().file.clone();
+        let written = fs::read_to_string(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert_ne!(file, original);
+        assert_eq!(Path::new(&file).parent(), Some(Path::new(LEVELS_DIRECTORY)));
+        assert_eq!(simulation.world.read_resource::<Editor>().map.origin.file, file);
+        assert!(written.is_ok());
+        assert_eq!(fs::read_to_string(&original).unwrap(), "#####\n#@$.#\n#####\n");
+        fs::remove_file(&original).unwrap();
+    }
+}
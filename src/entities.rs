@@ -6,7 +6,7 @@ pub fn create_wall(world: &mut World, position: Position) {
     world
         .create_entity()
         .with(Position { z: 10, ..position })
-        .with(Renderable::new_static("/images/wall.png".to_string()))
+        .with(Renderable { role: TileRole::Wall })
         .with(Immovable)
         .with(Wall {})
         .build();
@@ -16,7 +16,7 @@ pub fn create_floor(world: &mut World, position: Position) {
     world
         .create_entity()
         .with(Position { z: 5, ..position })
-        .with(Renderable::new_static("/images/floor.png".to_string()))
+        .with(Renderable { role: TileRole::Floor })
         .build();
 }
 
@@ -24,10 +24,7 @@ pub fn create_box(world: &mut World, position: Position, colour: BoxColour) {
     world
         .create_entity()
         .with(Position { z: 10, ..position })
-        .with(Renderable::new_animated(vec![
-            format!("/images/box_{}_1.png", colour).to_string(),
-            format!("/images/box_{}_2.png", colour).to_string(),
-        ]))
+        .with(Renderable { role: TileRole::Box(colour) })
         .with(Movable)
         .with(Box { colour })
         .build();
@@ -37,7 +34,7 @@ pub fn create_box_spot(world: &mut World, position: Position, colour: BoxColour)
     world
         .create_entity()
         .with(Position { z: 9, ..position })
-        .with(Renderable::new_static(format!("/images/box_spot_{}.png", colour).to_string()))
+        .with(Renderable { role: TileRole::Spot(colour) })
         .with(BoxSpot { colour })
         .build();
 }
@@ -46,11 +43,7 @@ pub fn create_player(world: &mut World, position: Position) {
     world
         .create_entity()
         .with(Position { z: 10, ..position })
-        .with(Renderable::new_animated(vec![
-            "/images/player_1.png".to_string(),
-            "/images/player_2.png".to_string(),
-            "/images/player_3.png".to_string(),
-        ]))
+        .with(Renderable { role: TileRole::Player })
         .with(Movable)
         .with(Player {})
         .build();
@@ -0,0 +1,132 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+
+use crate::components::BoxColour;
+
+// Where something is in a level file. Lines and columns start at 1, columns
+// count map cells (characters in XSB, tokens in our own format).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Location {
+    pub fn new(file: &str, line: usize, column: usize) -> Self {
+        Self { file: file.to_string(), line, column }
+    }
+
+    // Location of the cell that is x columns and y lines away from this one
+    pub fn offset(&self, x: u8, y: u8) -> Self {
+        Self {
+            file: self.file.clone(),
+            line: self.line + y as usize,
+            column: self.column + x as usize,
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LevelError {
+    // The file could not be read
+    Io { file: String, message: String },
+
+    // No collection or level with this name
+    NotFound { name: String },
+
+    // The collection file holds no level at all
+    NoLevels { file: String },
+
+    UnrecognizedItem { location: Location, item: String },
+    InvalidXml { location: Location, message: String },
+    TooLarge { location: Location, width: usize, height: usize },
+
+    // Structural checks of a parsed level
+    PlayerCount { location: Location, count: usize },
+    BoxCount { location: Location, colour: BoxColour, boxes: usize, spots: usize },
+    NotEnclosed { location: Location },
+    Unreachable { location: Location },
+
+    // The level uses something the file format it is written in has no item for
+    CannotWrite { location: Location, format: &'static str, reason: String },
+}
+
+impl Display for LevelError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Io { file, message } => write!(fmt, "{}: {}", file, message),
+            LevelError::NotFound { name } => write!(fmt, "{} not found", name),
+            LevelError::NoLevels { file } => write!(fmt, "{}: no levels found", file),
+            LevelError::UnrecognizedItem { location, item } => {
+                write!(fmt, "{}: unrecognized map item '{}'", location, item)
+            }
+            LevelError::InvalidXml { location, message } => {
+                write!(fmt, "{}: invalid XML: {}", location, message)
+            }
+            LevelError::TooLarge { location, width, height } => {
+                write!(fmt, "{}: level is too large ({}x{})", location, width, height)
+            }
+            LevelError::PlayerCount { location, count } => {
+                write!(fmt, "{}: expected exactly one player, found {}", location, count)
+            }
+            LevelError::BoxCount { location, colour, boxes, spots } => write!(
+                fmt,
+                "{}: {} {} boxes but {} {} spots",
+                location, boxes, colour, spots, colour
+            ),
+            LevelError::NotEnclosed { location } => {
+                write!(fmt, "{}: level is not enclosed by walls", location)
+            }
+            LevelError::Unreachable { location } => {
+                write!(fmt, "{}: the player cannot reach this cell", location)
+            }
+            LevelError::CannotWrite { location, format, reason } => {
+                write!(fmt, "{}: cannot be written as {}: {}", location, format, reason)
+            }
+        }
+    }
+}
+
+impl Error for LevelError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SaveError {
+    // The save file could not be read or written
+    Io { file: String, message: String },
+
+    // The save file is not what we wrote
+    Format { file: String, message: String },
+
+    // Written by a newer version of the game
+    Version { file: String, version: u32 },
+
+    // The saved level cannot be loaded any more
+    Level(LevelError),
+
+    // The level was edited since the game was saved
+    LevelChanged { title: String },
+}
+
+impl Display for SaveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io { file, message } => write!(fmt, "{}: {}", file, message),
+            SaveError::Format { file, message } => write!(fmt, "{}: invalid save: {}", file, message),
+            SaveError::Version { file, version } => {
+                write!(fmt, "{}: save format version {} is not supported", file, version)
+            }
+            SaveError::Level(error) => write!(fmt, "saved level: {}", error),
+            SaveError::LevelChanged { title } => write!(fmt, "level {} changed since it was saved", title),
+        }
+    }
+}
+
+impl Error for SaveError {}
@@ -1,16 +1,16 @@
 pub type EntityId = u32;
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct EntityMoved {
     pub id: EntityId,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BoxPlacedOnSpot {
     pub is_correct_spot: bool,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Event {
     // Fired when the player hits an obstacle like a wall
     PlayerHitObstacle,
@@ -23,4 +23,7 @@ pub enum Event {
 
     // Fired when the box is placed on a spot
     BoxPlacedOnSpot(BoxPlacedOnSpot),
+
+    // Fired when a move leaves boxes that can never reach their spots
+    Deadlocked,
 }
\ No newline at end of file
@@ -0,0 +1,187 @@
+use std::time::Duration;
+
+use specs::{World, WorldExt};
+
+use crate::actions::Action;
+use crate::components::Direction;
+use crate::config::GamepadConfig;
+use crate::resources::{InputQueue, Time};
+
+// Axes that move the player, named like gilrs names them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    LeftStickX,
+    LeftStickY,
+    DPadX,
+    DPadY,
+}
+
+// Directions held on the gamepads, turned into moves that repeat while held
+#[derive(Default)]
+pub struct Gamepad {
+    pub deadzone: f32,
+    pub repeat_delay: Duration,
+    pub repeat_interval: Duration,
+    // Stick and d-pad axes, x to the right and y up like gilrs reports them
+    stick: (f32, f32),
+    dpad_axes: (f32, f32),
+    // D-pad buttons held, the last one pressed wins
+    pressed: Vec<Direction>,
+    // Direction moving and when it moves next
+    held: Option<(Direction, Duration)>,
+}
+
+impl Gamepad {
+    pub fn new(config: &GamepadConfig) -> Self {
+        Self {
+            deadzone: config.deadzone.clamp(0.0, 0.95),
+            repeat_delay: Duration::from_millis(config.repeat_delay_ms),
+            repeat_interval: Duration::from_millis(config.repeat_interval_ms.max(1)),
+            ..Self::default()
+        }
+    }
+
+    pub fn axis(&mut self, axis: Axis, value: f32) {
+        match axis {
+            Axis::LeftStickX => self.stick.0 = value,
+            Axis::LeftStickY => self.stick.1 = value,
+            Axis::DPadX => self.dpad_axes.0 = value,
+            Axis::DPadY => self.dpad_axes.1 = value,
+        }
+    }
+
+    pub fn press(&mut self, direction: Direction) {
+        self.pressed.retain(|pressed| *pressed != direction);
+        self.pressed.push(direction);
+    }
+
+    pub fn release(&mut self, direction: Direction) {
+        self.pressed.retain(|pressed| *pressed != direction);
+    }
+
+    // Where the player is asked to go: d-pad buttons first, then the d-pad
+    // axes and the stick along its stronger axis past the deadzone
+    pub fn direction(&self) -> Option<Direction> {
+        if let Some(direction) = self.pressed.last() {
+            return Some(*direction);
+        }
+        [self.dpad_axes, self.stick].iter().find_map(|(x, y)| {
+            if x.abs().max(y.abs()) <= self.deadzone {
+                None
+            } else if x.abs() >= y.abs() {
+                Some(if *x > 0.0 { Direction::Right } else { Direction::Left })
+            } else {
+                Some(if *y > 0.0 { Direction::Up } else { Direction::Down })
+            }
+        })
+    }
+
+    // Move due at the given time: right away for a new direction, then after
+    // the delay and every interval while it stays held
+    pub fn next_move(&mut self, now: Duration) -> Option<Direction> {
+        let direction = self.direction();
+        match (direction, self.held) {
+            (None, _) => {
+                self.held = None;
+                None
+            }
+            (Some(direction), Some((held, next))) if direction == held => {
+                if now < next {
+                    return None;
+                }
+                self.held = Some((direction, next.max(now.saturating_sub(self.repeat_interval)) + self.repeat_interval));
+                Some(direction)
+            }
+            (Some(direction), _) => {
+                self.held = Some((direction, now + self.repeat_delay));
+                Some(direction)
+            }
+        }
+    }
+}
+
+// Queues the moves of the gamepads like key presses
+pub fn update_gamepad(world: &mut World) {
+    let now = world.read_resource::<Time>().delta;
+    if let Some(direction) = world.write_resource::<Gamepad>().next_move(now) {
+        world.write_resource::<InputQueue>().actions.push(Action::Move(direction));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gamepad() -> Gamepad {
+        let config = GamepadConfig { deadzone: 0.5, repeat_delay_ms: 300, repeat_interval_ms: 100, ..GamepadConfig::default() };
+        Gamepad::new(&config)
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn stick_inside_the_deadzone_does_nothing() {
+        let mut gamepad = gamepad();
+        gamepad.axis(Axis::LeftStickX, 0.4);
+        gamepad.axis(Axis::LeftStickY, -0.5);
+        assert_eq!(gamepad.direction(), None);
+
+        gamepad.axis(Axis::LeftStickX, 0.6);
+        assert_eq!(gamepad.direction(), Some(Direction::Right));
+    }
+
+    #[test]
+    fn stronger_stick_axis_wins() {
+        let mut gamepad = gamepad();
+        gamepad.axis(Axis::LeftStickX, -0.6);
+        gamepad.axis(Axis::LeftStickY, 0.8);
+        assert_eq!(gamepad.direction(), Some(Direction::Up));
+        gamepad.axis(Axis::LeftStickY, -0.8);
+        assert_eq!(gamepad.direction(), Some(Direction::Down));
+    }
+
+    #[test]
+    fn buttons_come_before_the_stick() {
+        let mut gamepad = gamepad();
+        gamepad.axis(Axis::LeftStickX, 1.0);
+        gamepad.press(Direction::Up);
+        gamepad.press(Direction::Left);
+        assert_eq!(gamepad.direction(), Some(Direction::Left));
+        gamepad.release(Direction::Left);
+        assert_eq!(gamepad.direction(), Some(Direction::Up));
+        gamepad.release(Direction::Up);
+        assert_eq!(gamepad.direction(), Some(Direction::Right));
+    }
+
+    #[test]
+    fn held_direction_repeats_after_the_delay() {
+        let mut gamepad = gamepad();
+        gamepad.press(Direction::Down);
+        assert_eq!(gamepad.next_move(ms(0)), Some(Direction::Down));
+        assert_eq!(gamepad.next_move(ms(299)), None);
+        assert_eq!(gamepad.next_move(ms(300)), Some(Direction::Down));
+        assert_eq!(gamepad.next_move(ms(399)), None);
+        assert_eq!(gamepad.next_move(ms(400)), Some(Direction::Down));
+        // After a slow frame one missed move follows right away, no more
+        assert_eq!(gamepad.next_move(ms(750)), Some(Direction::Down));
+        assert_eq!(gamepad.next_move(ms(760)), Some(Direction::Down));
+        assert_eq!(gamepad.next_move(ms(770)), None);
+        assert_eq!(gamepad.next_move(ms(860)), Some(Direction::Down));
+    }
+
+    #[test]
+    fn new_direction_moves_right_away() {
+        let mut gamepad = gamepad();
+        gamepad.press(Direction::Down);
+        assert_eq!(gamepad.next_move(ms(0)), Some(Direction::Down));
+        gamepad.press(Direction::Left);
+        assert_eq!(gamepad.next_move(ms(50)), Some(Direction::Left));
+        gamepad.release(Direction::Left);
+        gamepad.release(Direction::Down);
+        assert_eq!(gamepad.next_move(ms(60)), None);
+        gamepad.press(Direction::Down);
+        assert_eq!(gamepad.next_move(ms(70)), Some(Direction::Down));
+    }
+}
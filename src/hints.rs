@@ -0,0 +1,221 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use specs::{Join, World, WorldExt};
+
+use crate::components::{Box, Direction, Player, Position};
+use crate::constants::HINT_TIME_BUDGET;
+use crate::map::{self, LevelMap};
+use crate::resources::{Hint, HintStatus};
+use crate::solver::{self, SolverMode, SolverResult};
+
+// Solver running on its own thread for a hint. Dropping it tells the solver
+// to stop, so a search nobody waits for any more does not go on in the background.
+pub struct HintSearch {
+    handle: Option<JoinHandle<SolverResult>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl HintSearch {
+    // The solver gives up once the budget is spent
+    fn start(level_map: LevelMap, budget: Duration) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&cancelled);
+        let handle = thread::spawn(move || {
+            let deadline = Instant::now() + budget;
+            solver::search(&level_map, SolverMode::Coloured, &mut || {
+                stop.load(Ordering::Relaxed) || Instant::now() >= deadline
+            })
+        });
+        Self { handle: Some(handle), cancelled }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|handle| handle.is_finished())
+    }
+
+    fn join(mut self) -> SolverResult {
+        self.handle
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or(SolverResult::GaveUp)
+    }
+}
+
+impl Drop for HintSearch {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Player position followed by every box position, to tell whether a hint still applies
+fn current_layout(world: &World) -> Vec<(u8, u8)> {
+    let positions = world.read_storage::<Position>();
+    let mut boxes = (&positions, &world.read_storage::<Box>())
+        .join()
+        .map(|(position, _)| (position.x, position.y))
+        .collect::<Vec<_>>();
+    boxes.sort_unstable();
+
+    let mut layout = (&positions, &world.read_storage::<Player>())
+        .join()
+        .map(|(position, _)| (position.x, position.y))
+        .collect::<Vec<_>>();
+    layout.append(&mut boxes);
+    layout
+}
+
+// Turns the first step of the solution into something to show
+fn first_move(result: SolverResult, layout: &[(u8, u8)]) -> HintStatus {
+    let solution = match result {
+        SolverResult::Solved(solution) => solution,
+        SolverResult::Unsolvable => return HintStatus::NoSolution,
+        SolverResult::GaveUp => return HintStatus::GaveUp,
+    };
+
+    match (solution.moves.chars().next().and_then(Direction::from_lurd), layout.first()) {
+        (Some((direction, push)), Some((x, y))) => {
+            let (dx, dy) = direction.offset();
+            HintStatus::Move {
+                direction,
+                push,
+                x: (*x as i16 + dx as i16) as u8,
+                y: (*y as i16 + dy as i16) as u8,
+            }
+        }
+        // Nothing left to do, the level is already solved
+        _ => HintStatus::Hidden,
+    }
+}
+
+// Starts the solver when a hint is requested and picks up its answer. The
+// search runs on its own thread within HINT_TIME_BUDGET, so frames keep coming.
+pub fn update_hint(world: &mut World) {
+    let layout = current_layout(world);
+    let requested = {
+        let mut hint = world.write_resource::<Hint>();
+
+        // The player moved on, whatever was shown or being computed is stale.
+        // Dropping the search stops the solver.
+        if hint.status != HintStatus::Hidden && hint.layout != layout {
+            hint.status = HintStatus::Hidden;
+            hint.search = None;
+        }
+
+        if hint.search.as_ref().is_some_and(|search| search.is_finished()) {
+            if let Some(search) = hint.search.take() {
+                hint.status = first_move(search.join(), &hint.layout);
+            }
+        }
+
+        let requested = hint.requested && hint.search.is_none();
+        hint.requested = false;
+        requested
+    };
+
+    if requested {
+        let search = HintSearch::start(map::current_level_map(world), HINT_TIME_BUDGET);
+
+        let mut hint = world.write_resource::<Hint>();
+        hint.status = HintStatus::Thinking;
+        hint.layout = layout;
+        hint.search = Some(search);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::Action;
+    use crate::map::test_level;
+    use crate::simulation::{test_simulation, Simulation};
+
+    // Four frozen boxes in the middle of an open room, with four more to push
+    // around: the solver never finds a way and has plenty to look at
+    const ENDLESS: &str = "\
+############
+#@         #
+# $  $  $  #
+#    $$    #
+#    $$  $ #
+#          #
+#........  #
+############";
+
+    // Runs the frames until the hint is no longer being thought about
+    fn wait_for_hint(simulation: &mut Simulation) -> HintStatus {
+        let deadline = Instant::now() + HINT_TIME_BUDGET * 2;
+        while Instant::now() < deadline {
+            simulation.update();
+            let status = simulation.world.read_resource::<Hint>().status;
+            if status != HintStatus::Thinking {
+                return status;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("expected the hint search to finish");
+    }
+
+    #[test]
+    fn first_move_points_at_the_tile_to_step_on() {
+        let level_map = test_level("#####\n#@$.#\n#####");
+        let result = solver::solve(&level_map, SolverMode::Coloured);
+        let hint = first_move(result, &[(1, 1), (2, 1)]);
+        assert_eq!(hint, HintStatus::Move { direction: Direction::Right, push: true, x: 2, y: 1 });
+    }
+
+    #[test]
+    fn hint_is_found_in_the_background() {
+        let mut simulation = test_simulation("######\n#@ $.#\n######");
+        simulation.step(Action::Hint);
+        assert_eq!(wait_for_hint(&mut simulation), HintStatus::Move {
+            direction: Direction::Right,
+            push: false,
+            x: 2,
+            y: 1,
+        });
+        assert!(simulation.world.read_resource::<Hint>().search.is_none());
+    }
+
+    #[test]
+    fn deadlocked_position_has_no_solution() {
+        let mut simulation = test_simulation("#####\n#@ .#\n#$  #\n#####");
+        simulation.step(Action::Hint);
+        assert_eq!(wait_for_hint(&mut simulation), HintStatus::NoSolution);
+    }
+
+    #[test]
+    fn search_gives_up_when_the_budget_is_spent() {
+        let started = Instant::now();
+        let search = HintSearch::start(test_level(ENDLESS), Duration::from_millis(50));
+        assert!(matches!(search.join(), SolverResult::GaveUp));
+        assert!(started.elapsed() < HINT_TIME_BUDGET);
+    }
+
+    #[test]
+    fn dropped_search_stops_the_solver() {
+        let started = Instant::now();
+        let mut search = HintSearch::start(test_level(ENDLESS), HINT_TIME_BUDGET);
+        let handle = search.handle.take().unwrap();
+        drop(search);
+        assert!(matches!(handle.join().unwrap(), SolverResult::GaveUp));
+        assert!(started.elapsed() < HINT_TIME_BUDGET);
+    }
+
+    #[test]
+    fn stale_search_publishes_nothing() {
+        let mut simulation = test_simulation(ENDLESS);
+        simulation.step(Action::Hint);
+        assert_eq!(simulation.world.read_resource::<Hint>().status, HintStatus::Thinking);
+
+        simulation.step(Action::Move(Direction::Right));
+        for _ in 0..10 {
+            simulation.update();
+        }
+        let hint = simulation.world.read_resource::<Hint>();
+        assert_eq!(hint.status, HintStatus::Hidden);
+        assert!(hint.search.is_none());
+    }
+}
@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+
+use specs::{World, WorldExt};
+
+use crate::camera::Camera;
+use crate::constants::{
+    MAX_BOARD_HEIGHT, MAX_BOARD_WIDTH, STATE_DLMR_HEIGHT, STATE_DLMR_WIDTH, STATE_HEIGHT, STATE_WIDTH, TILE_SIZE,
+};
+use crate::resources::MapSize;
+
+// Width of a column of HUD text, margin included
+const HUD_COLUMN_WIDTH: f32 = (STATE_DLMR_WIDTH + STATE_WIDTH) as f32 * TILE_SIZE;
+
+// Part of the window or of an image, in pixels from its top left corner
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Self { x, y, w, h }
+    }
+
+    // Edges count as inside
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x <= self.x + self.w && y >= self.y && y <= self.y + self.h
+    }
+}
+
+// Where the board and the HUD go in a window of the given size, in window
+// coordinates (logical pixels, so high-DPI screens get the same layout)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Layout {
+    pub window: (f32, f32),
+    pub board: Rect,
+    pub hud: Rect,
+    // HUD under the board in tall windows, beside it otherwise
+    pub hud_below: bool,
+    // HUD lines per column, the lines run on in the next column
+    pub rows: u8,
+}
+
+impl Layout {
+    // Puts the HUD beside or below the board, wherever the level can be
+    // shown larger
+    pub fn new(window: (f32, f32), map_size: &MapSize) -> Self {
+        let (width, height) = window;
+
+        let beside = Self {
+            window,
+            board: Rect::new(0.0, 0.0, (width - HUD_COLUMN_WIDTH).max(0.0), height),
+            hud: Rect::new((width - HUD_COLUMN_WIDTH).max(0.0), 0.0, HUD_COLUMN_WIDTH.min(width), height),
+            hud_below: false,
+            rows: STATE_HEIGHT,
+        };
+
+        // As many columns as fit across, as few rows as they need
+        let columns = ((width / HUD_COLUMN_WIDTH) as u8).clamp(1, STATE_HEIGHT);
+        let rows = STATE_HEIGHT.div_ceil(columns);
+        let hud_height = ((rows + 2 * STATE_DLMR_HEIGHT) as f32 * TILE_SIZE).min(height);
+        let below = Self {
+            window,
+            board: Rect::new(0.0, 0.0, width, height - hud_height),
+            hud: Rect::new(0.0, height - hud_height, width, hud_height),
+            hud_below: true,
+            rows,
+        };
+
+        let fit = |board: Rect| {
+            let map_width = map_size.width.max(1) as f32 * TILE_SIZE;
+            let map_height = map_size.height.max(1) as f32 * TILE_SIZE;
+            (board.w / map_width).min(board.h / map_height)
+        };
+        if fit(below.board) > fit(beside.board) {
+            below
+        } else {
+            beside
+        }
+    }
+
+    // Window position of a line of HUD text
+    pub fn hud_line(&self, line: u8) -> (f32, f32) {
+        let rows = self.rows.max(1);
+        let (column, row) = (line / rows, line % rows);
+        (
+            self.hud.x + STATE_DLMR_WIDTH as f32 * TILE_SIZE + column as f32 * HUD_COLUMN_WIDTH,
+            self.hud.y + (STATE_DLMR_HEIGHT + row) as f32 * TILE_SIZE,
+        )
+    }
+}
+
+// Board part of the window a level asks for, in tiles
+fn board_tiles(map_size: &MapSize) -> (u8, u8) {
+    let state_height_tiles = STATE_HEIGHT + STATE_DLMR_HEIGHT;
+    let height = match map_size.height.cmp(&state_height_tiles) {
+        Ordering::Less => state_height_tiles,
+        _ => map_size.height
+    };
+    (map_size.width.min(MAX_BOARD_WIDTH), height.min(MAX_BOARD_HEIGHT))
+}
+
+// Window size that shows a level at full size with the HUD beside it, up to
+// a point, the camera takes care of larger ones
+pub fn window_size(map_size: &MapSize) -> (f32, f32) {
+    let (board_width, board_height) = board_tiles(map_size);
+    let width = (board_width as u16 + STATE_DLMR_WIDTH as u16 + STATE_WIDTH as u16) as f32 * TILE_SIZE;
+    (width, board_height as f32 * TILE_SIZE)
+}
+
+// Lays out the window for the current level and shows the board in its part
+pub fn update_layout(world: &mut World) {
+    let map_size = *world.read_resource::<MapSize>();
+    let window = world.read_resource::<Layout>().window;
+    let layout = Layout::new(window, &map_size);
+    world.write_resource::<Camera>().viewport = layout.board;
+    *world.write_resource::<Layout>() = layout;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map_size(width: u8, height: u8) -> MapSize {
+        MapSize { width, height }
+    }
+
+    #[test]
+    fn hud_beside_the_board_in_wide_windows() {
+        let layout = Layout::new((800.0, 600.0), &map_size(10, 10));
+        assert!(!layout.hud_below);
+        assert_eq!(layout.board, Rect::new(0.0, 0.0, 608.0, 600.0));
+        assert_eq!(layout.hud, Rect::new(608.0, 0.0, 192.0, 600.0));
+        assert_eq!(layout.rows, STATE_HEIGHT);
+    }
+
+    #[test]
+    fn hud_below_the_board_in_tall_windows() {
+        let layout = Layout::new((400.0, 900.0), &map_size(10, 10));
+        assert!(layout.hud_below);
+        assert_eq!(layout.board, Rect::new(0.0, 0.0, 400.0, 676.0));
+        assert_eq!(layout.hud, Rect::new(0.0, 676.0, 400.0, 224.0));
+        // Two columns of five lines, the seventh line is the second of the second column
+        assert_eq!(layout.rows, 5);
+        assert_eq!(layout.hud_line(0), (32.0, 708.0));
+        assert_eq!(layout.hud_line(6), (224.0, 740.0));
+    }
+
+    #[test]
+    fn wide_levels_put_the_hud_below() {
+        let layout = Layout::new((800.0, 600.0), &map_size(40, 5));
+        assert!(layout.hud_below);
+    }
+
+    #[test]
+    fn window_fits_the_level_up_to_a_point() {
+        assert_eq!(window_size(&map_size(10, 10)), (512.0, 320.0));
+        // Room for the HUD lines however low the level
+        assert_eq!(window_size(&map_size(3, 3)), (288.0, 320.0));
+        assert_eq!(window_size(&map_size(30, 30)), (960.0, 576.0));
+    }
+}
@@ -1,10 +1,176 @@
-use std::fs;
+use specs::{World, WorldExt};
 
-pub fn load_level(level: u8) -> String {
-    let level_code = format!("{:02}", level);
-    let level_path = format!("./resources/levels/level_{}", level_code);
-    let level_source = fs::read_to_string(&level_path)
-        .expect(&format!("expected level loaded: {}", &level_path)[..]);
+use crate::collection::Collection;
+use crate::constants::{LEVEL_ADVANCE_DELAY, SUMMARY_DELAY};
+use crate::deadlock::DeadSquares;
+use crate::editor::Editor;
+use crate::errors::LevelError;
+use crate::map;
+use crate::screens::{Screen, Screens};
+use crate::resources::{EventQueue, Gameplay, GameplayState, InputQueue, LevelChange, CurrentMap, Hint, LevelLoadError, LevelQueue, MapSize, MoveHistory, Route, Time};
 
-    String::from(level_source)
-}
\ No newline at end of file
+pub fn level_exists(world: &World, level: usize) -> bool {
+    level < world.read_resource::<Collection>().levels.len()
+}
+
+// Throws away every entity of the current level and builds the given level
+// of the current collection from scratch, with fresh gameplay state and history
+pub fn start_level(world: &mut World, level: usize) {
+    world.delete_all();
+    world.maintain();
+
+    let started_at = world.read_resource::<Time>().delta;
+    *world.write_resource::<Gameplay>() = Gameplay {
+        level,
+        started_at,
+        ..Gameplay::default()
+    };
+    *world.write_resource::<MoveHistory>() = MoveHistory::default();
+    world.write_resource::<EventQueue>().events.clear();
+    world.write_resource::<InputQueue>().actions.clear();
+    *world.write_resource::<Hint>() = Hint::default();
+    *world.write_resource::<Route>() = Route::default();
+
+    // Broken levels are reported on screen, the player can still switch away
+    let level_map = world
+        .read_resource::<Collection>()
+        .levels
+        .get(level)
+        .ok_or_else(|| LevelError::NotFound { name: format!("level {}", level + 1) })
+        .and_then(|level_info| level_info.map.clone())
+        .and_then(|level_map| level_map.validate().map(|_| level_map));
+    match level_map {
+        Ok(level_map) => {
+            world.write_resource::<LevelLoadError>().error = None;
+            *world.write_resource::<MapSize>() = MapSize {
+                width: level_map.width,
+                height: level_map.height,
+            };
+            map::load_map(world, &level_map);
+            *world.write_resource::<DeadSquares>() = DeadSquares::new(&level_map);
+            world.write_resource::<CurrentMap>().map = level_map;
+        }
+        Err(error) => show_error(world, error),
+    }
+}
+
+// The error is shown in place of the level
+pub fn show_error(world: &mut World, error: LevelError) {
+    world.write_resource::<LevelLoadError>().error = Some(error);
+}
+
+// Makes the given collection current and starts one of its levels
+pub fn start_collection(world: &mut World, collection: Collection, level: usize) {
+    world.insert(collection);
+    start_level(world, level);
+}
+
+// Closes the summary of a won level for the next one, or for the level list
+// after the last one. A level test-played from the editor goes back to it.
+pub fn leave_summary(
+    screens: &mut Screens,
+    level_queue: &mut LevelQueue,
+    level: usize,
+    level_count: usize,
+    test_play: bool,
+) {
+    screens.pop();
+    if test_play {
+        screens.pop();
+    } else if level + 1 < level_count {
+        level_queue.change = Some(LevelChange::Next);
+    } else {
+        screens.replace(Screen::LevelSelect { selected: level });
+    }
+}
+
+// Applies the requested level change. A won level shows its summary after a
+// while and moves on by itself a while later.
+pub fn update_level(world: &mut World) {
+    let (level, change) = {
+        let gameplay = world.read_resource::<Gameplay>();
+        let time = world.read_resource::<Time>();
+        let mut screens = world.write_resource::<Screens>();
+        let mut level_queue = world.write_resource::<LevelQueue>();
+
+        match (gameplay.won_at, screens.current()) {
+            (Some(won_at), Screen::Playing) if gameplay.state == GameplayState::Won
+                && time.delta >= won_at + SUMMARY_DELAY => screens.push(Screen::WonSummary),
+            (Some(won_at), Screen::WonSummary) if time.delta >= won_at + LEVEL_ADVANCE_DELAY => {
+                let level_count = world.read_resource::<Collection>().levels.len();
+                let test_play = world.read_resource::<Editor>().stashed.is_some();
+                leave_summary(&mut screens, &mut level_queue, gameplay.level, level_count, test_play);
+            }
+            _ => (),
+        }
+        (gameplay.level, level_queue.change.take())
+    };
+
+    let target = match change {
+        Some(LevelChange::Restart) => Some(level),
+        Some(LevelChange::Next) => level.checked_add(1),
+        Some(LevelChange::Previous) => level.checked_sub(1),
+        Some(LevelChange::Select(level)) => Some(level),
+        None => None,
+    };
+
+    if let Some(target) = target.filter(|target| level_exists(world, *target)) {
+        start_level(world, target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::Action;
+    use crate::collection::LevelInfo;
+    use crate::components::Direction;
+    use crate::simulation::Simulation;
+
+    // Two levels won with a single push to the right
+    fn two_levels() -> Simulation {
+        let levels = (1..=2)
+            .map(|number| LevelInfo::new(format!("Level {}", number), Ok(map::test_level("#####\n#@$.#\n#####"))))
+            .collect();
+        let mut simulation = Simulation::default();
+        simulation.start(Collection { levels, ..Collection::default() }, 0);
+        simulation
+    }
+
+    fn wait(simulation: &mut Simulation, duration: std::time::Duration) {
+        simulation.world.write_resource::<Time>().delta += duration;
+        simulation.update();
+    }
+
+    #[test]
+    fn won_level_moves_on_by_itself() {
+        let mut simulation = two_levels();
+        simulation.step(Action::Move(Direction::Right));
+        assert_eq!(simulation.state(), GameplayState::Won);
+
+        wait(&mut simulation, SUMMARY_DELAY);
+        assert_eq!(simulation.world.read_resource::<Screens>().current(), Screen::WonSummary);
+        wait(&mut simulation, LEVEL_ADVANCE_DELAY - SUMMARY_DELAY);
+        assert_eq!(simulation.world.read_resource::<Screens>().current(), Screen::Playing);
+        assert_eq!((simulation.level(), simulation.state()), (1, GameplayState::Playing));
+    }
+
+    #[test]
+    fn summary_can_be_left_early() {
+        let mut simulation = two_levels();
+        simulation.step(Action::Move(Direction::Right));
+        wait(&mut simulation, SUMMARY_DELAY);
+        simulation.step(Action::Confirm);
+        assert_eq!(simulation.level(), 1);
+    }
+
+    #[test]
+    fn last_level_moves_on_to_the_list() {
+        let mut simulation = two_levels();
+        start_level(&mut simulation.world, 1);
+        simulation.step(Action::Move(Direction::Right));
+        wait(&mut simulation, SUMMARY_DELAY);
+        wait(&mut simulation, LEVEL_ADVANCE_DELAY - SUMMARY_DELAY);
+        assert_eq!(simulation.world.read_resource::<Screens>().current(), Screen::LevelSelect { selected: 1 });
+    }
+}
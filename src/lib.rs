@@ -0,0 +1,32 @@
+pub mod actions;
+pub mod animation;
+#[cfg(feature = "frontend")]
+pub mod audio;
+pub mod bindings;
+pub mod camera;
+pub mod collection;
+pub mod colours;
+pub mod components;
+pub mod config;
+pub mod constants;
+pub mod deadlock;
+pub mod editor;
+pub mod entities;
+pub mod errors;
+pub mod events;
+pub mod gamepad;
+pub mod hints;
+pub mod layout;
+pub mod levels;
+pub mod map;
+pub mod pathfinding;
+pub mod profile;
+pub mod resources;
+pub mod save;
+pub mod screens;
+pub mod simulation;
+pub mod skins;
+pub mod solver;
+pub mod systems;
+#[cfg(feature = "frontend")]
+pub mod textures;
@@ -1,29 +1,78 @@
-use std::cmp::Ordering;
-use std::path;
-
-use ggez;
-use ggez::{conf, Context, event, GameResult, timer};
-use ggez::event::{KeyCode, KeyMods};
-use specs::{RunNow, World, WorldExt};
-
-use crate::constants::{FIRST_LEVEL, MAP_HEIGHT, MAP_WIDTH, STATE_DLMR_HEIGHT, STATE_DLMR_WIDTH, STATE_HEIGHT, STATE_WIDTH, TILE_SIZE};
-use crate::resources::Time;
-
-mod resources;
-mod map;
-mod entities;
-mod constants;
-mod components;
-mod systems;
-mod events;
-mod audio;
-mod levels;
+use std::{env, path};
+
+use ggez::{conf, Context, event, GameResult, graphics, timer};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods, MouseButton};
+use ggez::input::mouse;
+use specs::{RunNow, WorldExt};
+
+use sokoban::actions::Action;
+use sokoban::animation::Animation;
+use sokoban::audio::{self, AudioStore};
+use sokoban::bindings::{self, Bindings};
+use sokoban::camera::Camera;
+use sokoban::collection::{self, Collection};
+use sokoban::colours;
+use sokoban::components::Direction;
+use sokoban::config::{self, Config};
+use sokoban::constants::{FIRST_LEVEL, MIN_WINDOW_HEIGHT, MIN_WINDOW_WIDTH};
+use sokoban::errors::LevelError;
+use sokoban::gamepad::{self, Gamepad};
+use sokoban::layout::{self, Layout};
+use sokoban::levels;
+use sokoban::profile::{self, Profile};
+use sokoban::save;
+use sokoban::screens::{Screen, Screens};
+use sokoban::resources::{InputQueue, LevelLoadError, MapSize, PushRule, Replay, Ruleset, Time};
+use sokoban::simulation::Simulation;
+use sokoban::skins::Skins;
+use sokoban::solver::{self, SolverMode, SolverResult};
+use sokoban::systems;
+use sokoban::textures::{self, TextureCache};
 
 // This struct will hold all our game state
-// For now there is nothing to be held, but we'll add
-// things shortly.
 struct Game {
-    world: World,
+    simulation: Simulation,
+    // Window size last asked for, to fit the current level
+    dimensions: (f32, f32),
+    // Once the player sized the window or went fullscreen it stays as it is
+    resized: bool,
+    fullscreen: bool,
+    // Tile last clicked or dragged over
+    dragged: Option<(u8, u8)>,
+    // Settings file as loaded, bindings are written back into it
+    config: Config,
+}
+
+impl Game {
+    // Queues a click on the tile under the mouse, once per tile while dragging
+    fn click(&mut self, x: f32, y: f32) {
+        let map_size = *self.simulation.world.read_resource::<MapSize>();
+        let tile = match self.simulation.world.read_resource::<Camera>().screen_to_tile(&map_size, x, y) {
+            Some(tile) => tile,
+            None => return,
+        };
+
+        if self.dragged != Some(tile) {
+            self.dragged = Some(tile);
+            let mut input_queue = self.simulation.world.write_resource::<InputQueue>();
+            input_queue.actions.push(Action::Click { x: tile.0, y: tile.1 });
+        }
+    }
+
+    fn toggle_fullscreen(&mut self, context: &mut Context) {
+        self.fullscreen = !self.fullscreen;
+        self.resized = true;
+        let mode = if self.fullscreen { conf::FullscreenType::Desktop } else { conf::FullscreenType::Windowed };
+        if let Err(error) = graphics::set_fullscreen(context, mode) {
+            println!("Cannot switch to fullscreen: {}", error);
+        }
+    }
+
+    fn save(&self) {
+        if let Err(error) = save::save_game(&self.simulation.world, save::SAVE_FILE) {
+            println!("Cannot save: {}", error);
+        }
+    }
 }
 
 // This is the main event loop. ggez tells us to implement
@@ -32,82 +81,413 @@ struct Game {
 // - rendering
 impl event::EventHandler for Game {
     fn update(&mut self, context: &mut Context) -> GameResult {
-        // Run input system
+        // Get and update time resource
         {
-            let mut is = systems::InputSystem {};
-            is.run_now(&self.world);
+            let mut time = self.simulation.world.write_resource::<Time>();
+            time.delta += timer::delta(context);
         }
 
-        // Run gameplay state system
+        // Run the game rules and play what happened
+        let events = self.simulation.update();
+        self.simulation.world.write_resource::<AudioStore>().play_events(&events);
+        if self.simulation.world.read_resource::<Screens>().quit {
+            self.save();
+            event::quit(context);
+        }
+
+        // Keep new best scores even if the game is not closed properly
         {
-            let mut gss = systems::GameplayStateSystem {};
-            gss.run_now(&self.world);
+            let mut profile = self.simulation.world.write_resource::<Profile>();
+            if profile.changed {
+                if let Err(error) = profile::save_profile(&profile, profile::PROFILE_FILE) {
+                    println!("Cannot save profile: {}", error);
+                }
+                profile.changed = false;
+            }
         }
 
-        // Get and update time resource
+        // Rebound keys are kept right away
         {
-            let mut time = self.world.write_resource::<Time>();
-            time.delta += timer::delta(context);
+            let mut bindings = self.simulation.world.write_resource::<Bindings>();
+            if bindings.changed {
+                bindings.write_config(&mut self.config);
+                if let Err(error) = config::save_config(&self.config, config::CONFIG_FILE) {
+                    println!("Cannot save config: {}", error);
+                }
+                bindings.changed = false;
+            }
         }
 
-        // Run event system
+        // So is the skin picked last
         {
-            let mut gss = systems::EventSystem {};
-            gss.run_now(&self.world);
+            let mut skins = self.simulation.world.write_resource::<Skins>();
+            if skins.changed {
+                self.config.skin = skins.current().id.clone();
+                if let Err(error) = config::save_config(&self.config, config::CONFIG_FILE) {
+                    println!("Cannot save config: {}", error);
+                }
+                skins.changed = false;
+            }
+        }
+
+        // Levels come in all sizes, fit the window to the new one unless the
+        // player picked a size, the layout is redone on the next update
+        let dimensions = layout::window_size(&self.simulation.world.read_resource::<MapSize>());
+        if !self.resized && dimensions != self.dimensions {
+            graphics::set_drawable_size(context, dimensions.0, dimensions.1)?;
+            graphics::set_screen_coordinates(context, graphics::Rect::new(0.0, 0.0, dimensions.0, dimensions.1))?;
+            self.simulation.world.write_resource::<Layout>().window = dimensions;
+            self.dimensions = dimensions;
         }
         Ok(())
     }
 
+    // Sizes come in logical pixels, drawing and the mouse use them too
+    fn resize_event(&mut self, context: &mut Context, width: f32, height: f32) {
+        if let Err(error) = graphics::set_screen_coordinates(context, graphics::Rect::new(0.0, 0.0, width, height)) {
+            println!("Cannot resize: {}", error);
+        }
+        self.simulation.world.write_resource::<Layout>().window = (width, height);
+        if (width, height) != self.dimensions {
+            self.resized = true;
+        }
+    }
+
     fn key_down_event(
         &mut self,
-        _context: &mut Context,
+        context: &mut Context,
         keycode: KeyCode,
-        _keymod: KeyMods,
+        keymod: KeyMods,
         _repeat: bool,
     ) {
-        let mut input_queue = self.world.write_resource::<resources::InputQueue>();
-        input_queue.keys_pressed.push(keycode);
+        let screen = self.simulation.world.read_resource::<Screens>().current();
+        let mut bindings = self.simulation.world.write_resource::<Bindings>();
+        let key = bindings::key(&bindings::input_name(keycode));
+        let action = match screen {
+            // The editor can always be left too
+            Screen::Editor => {
+                let (shift, ctrl) = (keymod.contains(KeyMods::SHIFT), keymod.contains(KeyMods::CTRL));
+                key.and_then(|key| bindings.editor_action(key, shift, ctrl)).or_else(|| menu_key_action(keycode))
+            }
+            Screen::Controls { waiting: true, .. } => {
+                bindings.captured_key = key;
+                None
+            }
+            Screen::Playing => key.and_then(|key| bindings.action(key)),
+            // Menus can always be left, whatever was unbound
+            _ => key.and_then(|key| bindings.action(key)).or_else(|| menu_key_action(keycode)),
+        };
+        drop(bindings);
+        match action {
+            Some(Action::ToggleFullscreen) => self.toggle_fullscreen(context),
+            Some(action) => self.simulation.world.write_resource::<InputQueue>().actions.push(action),
+            None => (),
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, context: &mut Context, button: Button, _id: GamepadId) {
+        let screen = self.simulation.world.read_resource::<Screens>().current();
+        let mut bindings = self.simulation.world.write_resource::<Bindings>();
+        let bound = bindings::button(&bindings::input_name(button));
+        let action = match screen {
+            Screen::Controls { waiting: true, .. } => {
+                bindings.captured_button = bound;
+                None
+            }
+            Screen::Playing => bound.and_then(|bound| bindings.button_action(bound)),
+            _ => bound.and_then(|bound| bindings.button_action(bound)).or_else(|| menu_button_action(button)),
+        };
+        drop(bindings);
+
+        // Directions repeat while held, the gamepad resource moves
+        match action {
+            Some(Action::Move(direction)) => self.simulation.world.write_resource::<Gamepad>().press(direction),
+            Some(Action::ToggleFullscreen) => self.toggle_fullscreen(context),
+            Some(action) => self.simulation.world.write_resource::<InputQueue>().actions.push(action),
+            None => (),
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, _context: &mut Context, button: Button, _id: GamepadId) {
+        let bindings = self.simulation.world.read_resource::<Bindings>();
+        let bound = bindings::button(&bindings::input_name(button));
+        let action = bound.and_then(|bound| bindings.button_action(bound)).or_else(|| menu_button_action(button));
+        if let Some(Action::Move(direction)) = action {
+            self.simulation.world.write_resource::<Gamepad>().release(direction);
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, _context: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        let axis = match axis {
+            Axis::LeftStickX => gamepad::Axis::LeftStickX,
+            Axis::LeftStickY => gamepad::Axis::LeftStickY,
+            Axis::DPadX => gamepad::Axis::DPadX,
+            Axis::DPadY => gamepad::Axis::DPadY,
+            _ => return,
+        };
+        self.simulation.world.write_resource::<Gamepad>().axis(axis, value);
+    }
+
+    fn mouse_button_down_event(&mut self, _context: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if button == MouseButton::Left {
+            self.dragged = None;
+            self.click(x, y);
+        }
+    }
+
+    // Dragging with the left button held paints in the editor, with the
+    // right one it pans the camera
+    fn mouse_motion_event(&mut self, context: &mut Context, x: f32, y: f32, dx: f32, dy: f32) {
+        if mouse::button_pressed(context, MouseButton::Right) {
+            self.simulation.world.write_resource::<Camera>().drag(dx, dy);
+        }
+        if mouse::button_pressed(context, MouseButton::Left)
+            && self.simulation.world.read_resource::<Screens>().current() == Screen::Editor
+        {
+            self.click(x, y);
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, _context: &mut Context, _x: f32, y: f32) {
+        let action = if y > 0.0 { Action::ZoomIn } else { Action::ZoomOut };
+        if y != 0.0 {
+            self.simulation.world.write_resource::<Camera>().apply(action);
+        }
+    }
+
+    fn quit_event(&mut self, _context: &mut Context) -> bool {
+        self.save();
+        false
     }
 
     fn draw(&mut self, context: &mut Context) -> GameResult {
+        // Images of a new level or skin are read before it is first drawn,
+        // the ones that cannot be read show as placeholders
+        for missing in textures::update_textures(&mut self.simulation.world, context) {
+            println!("Cannot load {}", missing);
+        }
+
         // Render game entities
         {
             let mut rs = systems::RenderingSystem { context };
-            rs.run_now(&self.world);
+            rs.run_now(&self.simulation.world);
         }
 
         Ok(())
     }
 }
 
-pub fn calculate_dimensions() -> (f32, f32) {
-    let state_height_tiles = STATE_HEIGHT + STATE_DLMR_HEIGHT;
-    let width = (MAP_WIDTH + STATE_DLMR_WIDTH + STATE_WIDTH) as f32 * TILE_SIZE;
-    let height = match MAP_HEIGHT.cmp(&state_height_tiles) {
-        Ordering::Less => state_height_tiles,
-        _ => MAP_HEIGHT
-    } as f32 * TILE_SIZE;
-    (width, height)
+// Keys the menus take even when they are bound to nothing
+fn menu_key_action(keycode: KeyCode) -> Option<Action> {
+    let action = match keycode {
+        KeyCode::Up => Action::Move(Direction::Up),
+        KeyCode::Down => Action::Move(Direction::Down),
+        KeyCode::Left => Action::Move(Direction::Left),
+        KeyCode::Right => Action::Move(Direction::Right),
+        KeyCode::Return => Action::Confirm,
+        KeyCode::Escape => Action::Back,
+        _ => return None,
+    };
+    Some(action)
+}
+
+// Gamepad buttons the menus take even when they are bound to nothing
+fn menu_button_action(button: Button) -> Option<Action> {
+    let action = match button {
+        Button::DPadUp => Action::Move(Direction::Up),
+        Button::DPadDown => Action::Move(Direction::Down),
+        Button::DPadLeft => Action::Move(Direction::Left),
+        Button::DPadRight => Action::Move(Direction::Right),
+        Button::South => Action::Confirm,
+        Button::East => Action::Back,
+        _ => return None,
+    };
+    Some(action)
+}
+
+// Returns the value following the given option on the command line
+fn arg_value(args: &[String], option: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == option)
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+}
+
+// Prints every collection with its levels and how well they were solved
+fn print_collections(profile: &Profile) {
+    for name in collection::list_collections() {
+        let collection = match collection::load_collection(&name) {
+            Ok(collection) => collection,
+            Err(error) => {
+                println!("{} - {}", name, error);
+                continue;
+            }
+        };
+        println!("{} - {} ({} levels)", name, collection.title, collection.levels.len());
+        for (index, level) in collection.levels.iter().enumerate() {
+            let author = level.author.as_deref().unwrap_or("unknown");
+            println!("  {:3}. {} by {}", index + 1, level.title, author);
+            if let Some(score) = profile.score(&collection.name, &level.title) {
+                println!(
+                    "       solved: {} moves, {} pushes, {:.1}s\n       {}",
+                    score.moves, score.pushes, score.seconds, score.solution
+                );
+            }
+            if let Err(error) = level.map.as_ref().map_err(|error| error.clone()).and_then(|map| map.validate()) {
+                println!("       {}", error);
+            }
+        }
+    }
+}
+
+// Runs the solver on the given levels and prints what it found
+fn print_solutions(collection: &Collection, levels: Vec<usize>, mode: SolverMode) {
+    for index in levels {
+        let level = &collection.levels[index];
+        print!("{:3}. {}: ", index + 1, level.title);
+        let level_map = match level.map.as_ref().map_err(|error| error.clone()).and_then(|map| map.validate().map(|_| map)) {
+            Ok(level_map) => level_map,
+            Err(error) => {
+                println!("{}", error);
+                continue;
+            }
+        };
+        match solver::solve(level_map, mode) {
+            SolverResult::Solved(solution) => println!(
+                "{} moves, {} pushes\n     {}",
+                solution.move_count, solution.push_count, solution.moves
+            ),
+            SolverResult::Unsolvable => println!("no solution"),
+            SolverResult::GaveUp => println!("gave up, too many positions"),
+        }
+    }
 }
 
 pub fn main() -> GameResult {
-    let mut world = World::new();
-    components::register_components(&mut world);
-    resources::register_resources(&mut world);
-    map::load_map(&mut world, levels::load_level(FIRST_LEVEL));
+    // Usage: sokoban [--list] [--collection NAME] [--level TITLE_OR_NUMBER] [--push-rule single|chain]
+    //                [--replay LURD] [--new] [--solve [--classic]]
+    let args: Vec<String> = env::args().collect();
+    let profile = profile::load_profile(profile::PROFILE_FILE).unwrap_or_else(|error| {
+        println!("Cannot load profile: {}", error);
+        Profile::default()
+    });
+    if args.iter().any(|arg| arg == "--list") {
+        print_collections(&profile);
+        return Ok(());
+    }
+
+    let collection_name = arg_value(&args, "--collection")
+        .unwrap_or_else(|| collection::DEFAULT_COLLECTION.to_string());
+    if args.iter().any(|arg| arg == "--solve") {
+        let mode = if args.iter().any(|arg| arg == "--classic") { SolverMode::Classic } else { SolverMode::Coloured };
+        match collection::load_collection(&collection_name) {
+            Ok(collection) => {
+                let levels = match arg_value(&args, "--level") {
+                    Some(name) => collection.find_level(&name).into_iter().collect(),
+                    None => (0..collection.levels.len()).collect(),
+                };
+                print_solutions(&collection, levels, mode);
+            }
+            Err(error) => println!("{}", error),
+        }
+        return Ok(());
+    }
+
+    let config = config::load_config(config::CONFIG_FILE).unwrap_or_else(|error| {
+        println!("Cannot load config: {}", error);
+        Config::default()
+    });
+
+    let mut simulation = Simulation::default();
+    simulation.world.insert(profile);
+
+    // Carry on where the last game was left, unless asked for something else
+    let choosing = ["--new", "--collection", "--level", "--replay"];
+    let resumed = !args.iter().any(|arg| choosing.contains(&arg.as_str()))
+        && save::save_exists(save::SAVE_FILE)
+        && match save::load_game(&mut simulation, save::SAVE_FILE) {
+            Ok(warnings) => {
+                warnings.iter().for_each(|warning| println!("{}", warning));
+                true
+            }
+            Err(error) => {
+                println!("Cannot resume: {}", error);
+                let profile = simulation.world.remove::<Profile>().unwrap_or_default();
+                simulation = Simulation::default();
+                simulation.world.insert(profile);
+                false
+            }
+        };
+
+    let world = &mut simulation.world;
+    if !resumed && arg_value(&args, "--push-rule").as_deref() == Some("chain") {
+        world.write_resource::<Ruleset>().push_rule = PushRule::Chain;
+    }
+    match collection::load_collection(&collection_name) {
+        _ if resumed => (),
+        Ok(collection) => match arg_value(&args, "--level") {
+            Some(name) => match collection.find_level(&name) {
+                Some(level) => levels::start_collection(world, collection, level),
+                None => {
+                    world.insert(collection);
+                    levels::show_error(world, LevelError::NotFound { name: format!("level {}", name) });
+                }
+            },
+            None if collection_name == collection::DEFAULT_COLLECTION => {
+                levels::start_collection(world, collection, FIRST_LEVEL)
+            }
+            None => levels::start_collection(world, collection, 0),
+        },
+        Err(error) => levels::show_error(world, error),
+    }
+    if let Some(error) = &world.read_resource::<LevelLoadError>().error {
+        println!("Level error: {}", error);
+    }
+    if let Some(lurd) = arg_value(&args, "--replay") {
+        world.insert(Replay::new(&lurd));
+    }
+
+    // Start on the title screen, straight on the board when a level was picked
+    let mut screens = Screens { stack: vec![Screen::Title { selected: 0 }], quit: false };
+    if ["--level", "--replay"].iter().any(|option| args.iter().any(|arg| arg == option)) {
+        screens.push(Screen::Playing);
+    } else if args.iter().any(|arg| arg == "--collection") {
+        screens.push(Screen::LevelSelect { selected: 0 });
+    }
+    world.insert(screens);
+    let (bindings, binding_warnings) = Bindings::from_config(&config);
+    let (skins, skin_warnings) = Skins::load(&config.skin);
+    for warning in colours::warnings().iter().chain(&binding_warnings).chain(&skin_warnings) {
+        println!("{}", warning);
+    }
+    world.insert(bindings);
+    world.insert(Gamepad::new(&config.gamepad));
+    world.insert(Animation::new(&config.animation));
+    world.insert(Camera::new(&config.camera));
+    world.insert(skins);
+    let dimensions = layout::window_size(&world.read_resource::<MapSize>());
+    world.write_resource::<Layout>().window = dimensions;
+    layout::update_layout(world);
 
     // Create a game context and event loop
-    let dimensions = calculate_dimensions();
+    let window_mode = conf::WindowMode::default()
+        .dimensions(dimensions.0, dimensions.1)
+        .min_dimensions(MIN_WINDOW_WIDTH, MIN_WINDOW_HEIGHT)
+        .resizable(true);
     let context_builder = ggez::ContextBuilder::new("rust_sokoban", "sokoban")
         .window_setup(conf::WindowSetup::default().title("Rust Sokoban!"))
-        .window_mode(conf::WindowMode::default().dimensions(dimensions.0, dimensions.1))
+        .window_mode(window_mode)
         .add_resource_path(path::PathBuf::from("./resources"));
 
     let (context, event_loop) = &mut context_builder.build()?;
-    audio::initialize_sounds(&mut world, context);
+    audio::initialize_sounds(world, context);
+    world.insert(TextureCache::default());
+
+    // Pixel art stays sharp at any scale
+    graphics::set_default_filter(context, graphics::FilterMode::Nearest);
 
     // Create the game state
-    let game = &mut Game { world };
+    let game = &mut Game { simulation, dimensions, resized: false, fullscreen: false, dragged: None, config };
     // Run the main event loop
     event::run(context, event_loop, game)
 }
\ No newline at end of file
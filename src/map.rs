@@ -1,48 +1,536 @@
-use specs::World;
+use std::collections::HashMap;
 
-use crate::components::{Position, BoxColour};
+use specs::{Join, ReadStorage, World, WorldExt};
+
+use crate::components::{Box, BoxColour, Player, Position};
 use crate::entities::*;
+use crate::errors::{LevelError, Location};
+use crate::resources::{CurrentMap, MapLoads};
 
-pub fn load_map(world: &mut World, map_string: String) {
-    let rows: Vec<&str> = map_string.trim().split('\n').map(|x| x.trim()).collect();
-    for (y, row) in rows.iter().enumerate() {
+// Everything that sits on one cell of a level
+#[derive(Clone, Default)]
+pub struct Tile {
+    pub floor: bool,
+    pub wall: bool,
+    pub player: bool,
+    pub box_colour: Option<BoxColour>,
+    pub spot_colour: Option<BoxColour>,
+}
+
+// Format independent level layout, stored row by row
+#[derive(Clone, Default)]
+pub struct LevelMap {
+    pub width: u8,
+    pub height: u8,
+    pub tiles: Vec<Tile>,
+    // Where the top left cell comes from, for error reporting
+    pub origin: Location,
+}
+
+impl LevelMap {
+    pub fn new(width: u8, height: u8, origin: Location) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![Tile::default(); width as usize * height as usize],
+            origin,
+        }
+    }
+
+    // Builds an empty map, refusing sizes our u8 positions cannot address
+    fn with_size(width: usize, height: usize, origin: Location) -> Result<Self, LevelError> {
+        if width > u8::MAX as usize || height > u8::MAX as usize {
+            return Err(LevelError::TooLarge { location: origin, width, height });
+        }
+        Ok(Self::new(width as u8, height as u8, origin))
+    }
+
+    pub fn tile(&self, x: u8, y: u8) -> &Tile {
+        &self.tiles[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn tile_mut(&mut self, x: u8, y: u8) -> &mut Tile {
+        &mut self.tiles[y as usize * self.width as usize + x as usize]
+    }
+
+    pub fn location(&self, x: u8, y: u8) -> Location {
+        self.origin.offset(x, y)
+    }
+
+    // Cells next to the given one, leaving out those outside of the map
+    pub fn neighbours(&self, x: u8, y: u8) -> Vec<(u8, u8)> {
+        let mut neighbours = Vec::with_capacity(4);
+        if x > 0 { neighbours.push((x - 1, y)); }
+        if y > 0 { neighbours.push((x, y - 1)); }
+        if x + 1 < self.width { neighbours.push((x + 1, y)); }
+        if y + 1 < self.height { neighbours.push((x, y + 1)); }
+        neighbours
+    }
+
+    fn is_border(&self, x: u8, y: u8) -> bool {
+        x == 0 || y == 0 || x + 1 == self.width || y + 1 == self.height
+    }
+
+    fn players(&self) -> Vec<(u8, u8)> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|(x, y)| self.tile(*x, *y).player)
+            .collect()
+    }
+
+    // Cells the player can walk to from the given one when boxes are ignored
+    fn walkable_from(&self, start: (u8, u8)) -> Vec<bool> {
+        let mut stack = vec![start];
+        let mut visited = vec![false; self.tiles.len()];
+        while let Some((x, y)) = stack.pop() {
+            let index = y as usize * self.width as usize + x as usize;
+            if visited[index] || self.tile(x, y).wall {
+                continue;
+            }
+            visited[index] = true;
+            stack.append(&mut self.neighbours(x, y));
+        }
+        visited
+    }
+
+    // Checks the level can actually be played: exactly one player, as many
+    // boxes as spots of every colour (boxes and spots of an any colour make up
+    // for missing ones), the player's area closed by walls and every box and
+    // spot inside it
+    pub fn validate(&self) -> Result<(), LevelError> {
+        let players = self.players();
+        if players.len() != 1 {
+            let location = players
+                .get(1)
+                .map_or_else(|| self.origin.clone(), |(x, y)| self.location(*x, *y));
+            return Err(LevelError::PlayerCount { location, count: players.len() });
+        }
+
+        let mut counts: HashMap<BoxColour, (usize, usize)> = HashMap::new();
+        for tile in self.tiles.iter() {
+            if let Some(colour) = tile.box_colour {
+                counts.entry(colour).or_default().0 += 1;
+            }
+            if let Some(colour) = tile.spot_colour {
+                counts.entry(colour).or_default().1 += 1;
+            }
+        }
+        let (any_boxes, any_spots) = counts
+            .iter()
+            .filter(|(colour, _)| colour.is_any())
+            .fold((0, 0), |(boxes, spots), (_, counts)| (boxes + counts.0, spots + counts.1));
+        let (all_boxes, all_spots) = counts.values().fold((0, 0), |(boxes, spots), counts| (boxes + counts.0, spots + counts.1));
+        let mut counts = counts.into_iter().collect::<Vec<_>>();
+        counts.sort_by_key(|(colour, _)| (colour.is_any(), colour.to_string()));
+        let unmatched = counts.into_iter().find(|(colour, (boxes, spots))| {
+            if colour.is_any() {
+                all_boxes != all_spots
+            } else {
+                *boxes > spots + any_spots || *spots > boxes + any_boxes
+            }
+        });
+        if let Some((colour, (boxes, spots))) = unmatched {
+            return Err(LevelError::BoxCount { location: self.origin.clone(), colour, boxes, spots });
+        }
+
+        let walkable = self.walkable_from(players[0]);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let tile = self.tile(x, y);
+                let index = y as usize * self.width as usize + x as usize;
+                if walkable[index] && (!tile.floor || self.is_border(x, y)) {
+                    return Err(LevelError::NotEnclosed { location: self.location(x, y) });
+                }
+                if !walkable[index] && (tile.box_colour.is_some() || tile.spot_colour.is_some()) {
+                    return Err(LevelError::Unreachable { location: self.location(x, y) });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
 
-        let cols: Vec<&str> = row.split(' ').collect();
+// Parses the project's own format: space separated tokens, one row per line.
+// The origin is where the map string starts in its file.
+pub fn parse_map(map_string: &str, origin: Location) -> Result<LevelMap, LevelError> {
+    let skipped_lines = map_string.lines().take_while(|row| row.trim().is_empty()).count();
+    let rows: Vec<Vec<&str>> = map_string
+        .trim()
+        .split('\n')
+        .map(|x| x.trim().split(' ').collect())
+        .collect();
+    let width = rows.iter().map(|cols| cols.len()).max().unwrap_or(0);
+    let origin = Location { line: origin.line + skipped_lines, ..origin };
+    let mut level_map = LevelMap::with_size(width, rows.len(), origin)?;
+
+    for (y, cols) in rows.iter().enumerate() {
         for (x, col) in cols.iter().enumerate() {
-            let pos = Position {
-                x: x as u8,
-                y: y as u8,
-                z: 0
-            };
+            let (x, y) = (x as u8, y as u8);
+            let location = level_map.location(x, y);
+            let tile = level_map.tile_mut(x, y);
+            tile.floor = *col != "N";
             match *col {
-                "." => create_floor(world, pos),
-                "W" => {
-                    create_floor(world, pos);
-                    create_wall(world, pos);
-                },
-                "P" => {
-                    create_floor(world, pos);
-                    create_player(world, pos);
-                },
-                "RB" => {
-                    create_floor(world, pos);
-                    create_box(world, pos, BoxColour::Red);
-                },
-                "BB" => {
-                    create_floor(world, pos);
-                    create_box(world, pos, BoxColour::Blue);
-                },
-                "RS" => {
-                    create_floor(world, pos);
-                    create_box_spot(world, pos, BoxColour::Red);
-                },
-                "BS" => {
-                    create_floor(world, pos);
-                    create_box_spot(world, pos, BoxColour::Blue);
-                },
+                "." => (),
+                "W" => tile.wall = true,
+                "P" => tile.player = true,
                 "N" => (),
-                c => panic!("unrecognized map item {}", c),
+                c => match coloured_item(c) {
+                    Some((colour, 'B')) => tile.box_colour = Some(colour),
+                    Some((colour, _)) => tile.spot_colour = Some(colour),
+                    None => return Err(LevelError::UnrecognizedItem { location, item: c.to_string() }),
+                },
+            }
+        }
+    }
+
+    Ok(level_map)
+}
+
+// Box (B) or spot (S) of a colour, written after the code of the colour
+fn coloured_item(item: &str) -> Option<(BoxColour, char)> {
+    let kind = item.chars().last().filter(|kind| *kind == 'B' || *kind == 'S')?;
+    let colour = BoxColour::from_code(&item[..item.len() - 1])?;
+    Some((colour, kind))
+}
+
+// Parses the community XSB format (#, @, +, $, *, ., with space, '-' or '_'
+// for empty cells). Empty cells only become floor if the player can walk to
+// them, so the outside of the level stays blank.
+pub fn parse_xsb(map_string: &str, origin: Location) -> Result<LevelMap, LevelError> {
+    let skipped_lines = map_string.lines().take_while(|row| row.trim().is_empty()).count();
+    let rows: Vec<&str> = map_string
+        .lines()
+        .map(|row| row.trim_end())
+        .skip_while(|row| row.is_empty())
+        .take_while(|row| !row.is_empty())
+        .collect();
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0);
+    let origin = Location { line: origin.line + skipped_lines, ..origin };
+    let mut level_map = LevelMap::with_size(width, rows.len(), origin)?;
+    let mut player = None;
+
+    for (y, row) in rows.iter().enumerate() {
+        for (x, item) in row.chars().enumerate() {
+            let (x, y) = (x as u8, y as u8);
+            let location = level_map.location(x, y);
+            let tile = level_map.tile_mut(x, y);
+            match item {
+                ' ' | '-' | '_' => (),
+                '#' => {
+                    tile.floor = true;
+                    tile.wall = true;
+                }
+                '@' | '+' => {
+                    tile.player = true;
+                    player = Some((x, y));
+                }
+                '$' => tile.box_colour = Some(BoxColour::first()),
+                '.' => tile.spot_colour = Some(BoxColour::first()),
+                '*' => {
+                    tile.box_colour = Some(BoxColour::first());
+                    tile.spot_colour = Some(BoxColour::first());
+                }
+                c => return Err(LevelError::UnrecognizedItem { location, item: c.to_string() }),
+            }
+            if item == '+' {
+                tile.spot_colour = Some(BoxColour::first());
+            }
+            if tile.player || tile.box_colour.is_some() || tile.spot_colour.is_some() {
+                tile.floor = true;
+            }
+        }
+    }
+
+    // Flood fill the inside of the level starting at the player
+    if let Some(player) = player {
+        let walkable = level_map.walkable_from(player);
+        for (tile, walkable) in level_map.tiles.iter_mut().zip(walkable) {
+            tile.floor |= walkable;
+        }
+    }
+
+    Ok(level_map)
+}
+
+// Parses an XSB board as if it started the file test.xsb, for tests
+#[cfg(test)]
+pub fn test_level(xsb: &str) -> LevelMap {
+    parse_xsb(xsb, Location::new("test.xsb", 1, 1)).expect("expected a valid XSB board")
+}
+
+// Writes the map in the project's own format. It has one item per cell, so
+// a box or the player standing on a spot cannot be written.
+pub fn write_map(level_map: &LevelMap) -> Result<String, LevelError> {
+    let mut map_string = String::new();
+    for y in 0..level_map.height {
+        let mut row = Vec::with_capacity(level_map.width as usize);
+        for x in 0..level_map.width {
+            let tile = level_map.tile(x, y);
+            let item = match (tile.wall, tile.player, tile.box_colour, tile.spot_colour) {
+                (true, _, _, _) => "W".to_string(),
+                (_, true, _, Some(_)) | (_, _, Some(_), Some(_)) => {
+                    return Err(LevelError::CannotWrite {
+                        location: level_map.location(x, y),
+                        format: "native map",
+                        reason: "only one item fits on a spot".to_string(),
+                    })
+                }
+                (_, true, _, _) => "P".to_string(),
+                (_, _, Some(colour), _) => format!("{}B", colour.info().code),
+                (_, _, _, Some(colour)) => format!("{}S", colour.info().code),
+                _ if tile.floor => ".".to_string(),
+                _ => "N".to_string(),
+            };
+            row.push(item);
+        }
+        map_string.push_str(&row.join(" "));
+        map_string.push('\n');
+    }
+    Ok(map_string)
+}
+
+// Writes the map in XSB. XSB has no colours, so every box and spot must share one.
+pub fn write_xsb(level_map: &LevelMap) -> Result<String, LevelError> {
+    let mut colours = level_map.tiles.iter().flat_map(|tile| tile.box_colour.into_iter().chain(tile.spot_colour));
+    if let Some(colour) = colours.next() {
+        if colours.any(|other| other != colour) {
+            return Err(LevelError::CannotWrite {
+                location: level_map.origin.clone(),
+                format: "XSB",
+                reason: "boxes and spots of more than one colour".to_string(),
+            });
+        }
+    }
+
+    let mut map_string = String::new();
+    for y in 0..level_map.height {
+        let row = (0..level_map.width)
+            .map(|x| {
+                let tile = level_map.tile(x, y);
+                match (tile.wall, tile.player, tile.box_colour.is_some(), tile.spot_colour.is_some()) {
+                    (true, _, _, _) => '#',
+                    (_, true, _, true) => '+',
+                    (_, true, _, false) => '@',
+                    (_, _, true, true) => '*',
+                    (_, _, true, false) => '$',
+                    (_, _, _, true) => '.',
+                    _ => ' ',
+                }
+            })
+            .collect::<String>();
+        map_string.push_str(row.trim_end());
+        map_string.push('\n');
+    }
+    Ok(map_string)
+}
+
+pub fn load_map(world: &mut World, level_map: &LevelMap) {
+    for y in 0..level_map.height {
+        for x in 0..level_map.width {
+            let tile = level_map.tile(x, y);
+            let pos = Position { x, y, z: 0 };
+
+            if tile.floor {
+                create_floor(world, pos);
+            }
+            if tile.wall {
+                create_wall(world, pos);
+            }
+            if let Some(colour) = tile.spot_colour {
+                create_box_spot(world, pos, colour);
+            }
+            if let Some(colour) = tile.box_colour {
+                create_box(world, pos, colour);
+            }
+            if tile.player {
+                create_player(world, pos);
             }
         }
     }
-}
\ No newline at end of file
+
+    // The front end loads the images of the new tiles before drawing them
+    world.write_resource::<MapLoads>().count += 1;
+}
+
+// The level as loaded, with boxes and player moved to where they are now
+pub fn with_current_layout(
+    loaded_map: &LevelMap,
+    positions: &ReadStorage<Position>,
+    boxes: &ReadStorage<Box>,
+    players: &ReadStorage<Player>,
+) -> LevelMap {
+    let mut level_map = loaded_map.clone();
+    for tile in level_map.tiles.iter_mut() {
+        tile.player = false;
+        tile.box_colour = None;
+    }
+
+    for (position, the_box) in (positions, boxes).join() {
+        level_map.tile_mut(position.x, position.y).box_colour = Some(the_box.colour);
+    }
+    for (position, _player) in (positions, players).join() {
+        level_map.tile_mut(position.x, position.y).player = true;
+    }
+    level_map
+}
+
+pub fn current_level_map(world: &World) -> LevelMap {
+    with_current_layout(
+        &world.read_resource::<CurrentMap>().map,
+        &world.read_storage::<Position>(),
+        &world.read_storage::<Box>(),
+        &world.read_storage::<Player>(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xsb_level() {
+        let level_map = test_level("  #####\n###   #\n#+$*  #\n#  $. #\n#######\n");
+        assert_eq!(level_map.width, 7);
+        assert_eq!(level_map.height, 5);
+        assert!(level_map.tile(1, 2).player);
+        assert_eq!(level_map.tile(1, 2).spot_colour, Some(BoxColour::first()));
+        assert_eq!(level_map.tile(3, 2).box_colour, Some(BoxColour::first()));
+        assert_eq!(level_map.tile(3, 2).spot_colour, Some(BoxColour::first()));
+        assert!(!level_map.tile(0, 0).floor);
+        assert!(level_map.tile(4, 1).floor);
+    }
+
+    #[test]
+    fn native_level() {
+        let map = "W W W W W\nW P RB RS W\nN W BB BS W\nN W W W W\n";
+        let level_map = parse_map(map, Location::new("test", 1, 1)).unwrap();
+        assert_eq!(level_map.width, 5);
+        assert_eq!(level_map.height, 4);
+        assert!(level_map.tile(1, 1).player);
+        assert_eq!(level_map.tile(2, 1).box_colour, BoxColour::named("red"));
+        assert_eq!(level_map.tile(3, 2).spot_colour, BoxColour::from_code("B"));
+        assert!(!level_map.tile(0, 2).floor);
+        assert_eq!(level_map.validate(), Ok(()));
+    }
+
+    #[test]
+    fn unrecognized_item() {
+        let error = parse_xsb("#####\n#@$x#\n#####", Location::new("test.xsb", 1, 1)).err();
+        assert_eq!(
+            error,
+            Some(LevelError::UnrecognizedItem { location: Location::new("test.xsb", 2, 4), item: "x".to_string() })
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let error = parse_xsb(&"#".repeat(300), Location::new("test.xsb", 1, 1)).err();
+        assert_eq!(
+            error,
+            Some(LevelError::TooLarge { location: Location::new("test.xsb", 1, 1), width: 300, height: 1 })
+        );
+    }
+
+    #[test]
+    fn valid_level() {
+        assert_eq!(test_level("#####\n#@$.#\n#####").validate(), Ok(()));
+    }
+
+    #[test]
+    fn second_player() {
+        let level_map = test_level("######\n#@$.@#\n######");
+        assert_eq!(
+            level_map.validate(),
+            Err(LevelError::PlayerCount { location: Location::new("test.xsb", 2, 5), count: 2 })
+        );
+    }
+
+    #[test]
+    fn no_player() {
+        let level_map = test_level("#####\n# $.#\n#####");
+        assert_eq!(
+            level_map.validate(),
+            Err(LevelError::PlayerCount { location: Location::new("test.xsb", 1, 1), count: 0 })
+        );
+    }
+
+    #[test]
+    fn box_count() {
+        let level_map = test_level("######\n#@$$.#\n######");
+        assert_eq!(
+            level_map.validate(),
+            Err(LevelError::BoxCount {
+                location: Location::new("test.xsb", 1, 1),
+                colour: BoxColour::first(),
+                boxes: 2,
+                spots: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn box_count_by_colour() {
+        let level_map = parse_map("W W W W W\nW P RB BS W\nW W W W W", Location::new("test", 1, 1)).unwrap();
+        assert_eq!(
+            level_map.validate(),
+            Err(LevelError::BoxCount {
+                location: level_map.origin.clone(),
+                colour: BoxColour::from_code("B").unwrap(),
+                boxes: 0,
+                spots: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn any_colour_makes_up_for_missing_spots() {
+        let level_map = parse_map("W W W W W\nW P RB AS W\nW W W W W", Location::new("test", 1, 1)).unwrap();
+        assert_eq!(level_map.validate(), Ok(()));
+    }
+
+    #[test]
+    fn not_enclosed() {
+        let level_map = test_level("#####\n#@$.\n#####");
+        assert_eq!(
+            level_map.validate(),
+            Err(LevelError::NotEnclosed { location: Location::new("test.xsb", 2, 5) })
+        );
+    }
+
+    #[test]
+    fn unreachable() {
+        let level_map = test_level("#####\n#@$.#$.\n#####");
+        assert_eq!(
+            level_map.validate(),
+            Err(LevelError::Unreachable { location: Location::new("test.xsb", 2, 6) })
+        );
+    }
+
+    #[test]
+    fn xsb_round_trip() {
+        let xsb = "  #####\n###   #\n#+$*  #\n#  $. #\n#######\n";
+        assert_eq!(write_xsb(&test_level(xsb)).unwrap(), xsb);
+    }
+
+    #[test]
+    fn native_round_trip() {
+        let map = "W W W W W W\nW P RB RS AB W\nW . BB BS AS W\nW W W W W W\n";
+        assert_eq!(write_map(&parse_map(map, Location::new("test", 1, 1)).unwrap()).unwrap(), map);
+    }
+
+    #[test]
+    fn native_cannot_write_player_on_spot() {
+        let level_map = test_level("#####\n#+$.#\n#####");
+        assert!(matches!(write_map(&level_map), Err(LevelError::CannotWrite { format: "native map", .. })));
+    }
+
+    #[test]
+    fn xsb_cannot_write_colours() {
+        let level_map = parse_map("W W W W W W\nW P RB RS W\nW W W W W W", Location::new("test", 1, 1)).unwrap();
+        let mut coloured = level_map.clone();
+        coloured.tile_mut(3, 1).spot_colour = BoxColour::from_code("B");
+        assert!(write_xsb(&level_map).is_ok());
+        assert!(matches!(write_xsb(&coloured), Err(LevelError::CannotWrite { format: "XSB", .. })));
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use specs::{World, WorldExt};
+
+use crate::components::Direction;
+use crate::events::Event;
+use crate::map::{self, LevelMap};
+use crate::resources::{EventQueue, Route};
+
+fn is_free(level_map: &LevelMap, (x, y): (u8, u8)) -> bool {
+    let tile = level_map.tile(x, y);
+    tile.floor && !tile.wall && tile.box_colour.is_none()
+}
+
+fn step(level_map: &LevelMap, (x, y): (u8, u8), direction: Direction) -> Option<(u8, u8)> {
+    let (dx, dy) = direction.offset();
+    let (x, y) = (x as i16 + dx as i16, y as i16 + dy as i16);
+    if x < 0 || y < 0 || x >= level_map.width as i16 || y >= level_map.height as i16 {
+        return None;
+    }
+    Some((x as u8, y as u8))
+}
+
+// Breadth first search from the start until is_goal, next gives the states
+// reachable with one step in the given direction
+fn shortest_path<S, N, G>(start: S, mut next: N, is_goal: G) -> Option<Vec<Direction>>
+where
+    S: Copy + Eq + Hash,
+    N: FnMut(S, Direction) -> Option<S>,
+    G: Fn(S) -> bool,
+{
+    let mut parents: HashMap<S, Option<(S, Direction)>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    parents.insert(start, None);
+    queue.push_back(start);
+
+    while let Some(state) = queue.pop_front() {
+        if is_goal(state) {
+            let mut path = Vec::new();
+            let mut current = state;
+            while let Some(Some((parent, direction))) = parents.get(&current) {
+                path.push(*direction);
+                current = *parent;
+            }
+            path.reverse();
+            return Some(path);
+        }
+
+        for direction in Direction::ALL.iter() {
+            if let Some(following) = next(state, *direction) {
+                if let Entry::Vacant(entry) = parents.entry(following) {
+                    entry.insert(Some((state, *direction)));
+                    queue.push_back(following);
+                }
+            }
+        }
+    }
+    None
+}
+
+fn player(level_map: &LevelMap) -> Option<(u8, u8)> {
+    (0..level_map.height)
+        .flat_map(|y| (0..level_map.width).map(move |x| (x, y)))
+        .find(|(x, y)| level_map.tile(*x, *y).player)
+}
+
+// Steps that take the player to the given cell without pushing anything
+pub fn walk_path(level_map: &LevelMap, to: (u8, u8)) -> Option<Vec<Direction>> {
+    let from = player(level_map)?;
+    if !is_free(level_map, to) {
+        return None;
+    }
+    shortest_path(
+        from,
+        |cell, direction| step(level_map, cell, direction).filter(|next| is_free(level_map, *next)),
+        |cell| cell == to,
+    )
+}
+
+// Steps that get the box to the given cell, walking around it as needed.
+// Other boxes stay where they are, only this one is pushed.
+pub fn push_path(level_map: &LevelMap, the_box: (u8, u8), to: (u8, u8)) -> Option<Vec<Direction>> {
+    let from = player(level_map)?;
+    let mut level_map = level_map.clone();
+    level_map.tile_mut(the_box.0, the_box.1).box_colour = None;
+    if !is_free(&level_map, to) {
+        return None;
+    }
+
+    shortest_path(
+        (the_box, from),
+        |(the_box, player), direction| {
+            let next = step(&level_map, player, direction)?;
+            if next == the_box {
+                let pushed = step(&level_map, the_box, direction).filter(|cell| is_free(&level_map, *cell))?;
+                Some((pushed, next))
+            } else if is_free(&level_map, next) {
+                Some((the_box, next))
+            } else {
+                None
+            }
+        },
+        |(the_box, _)| the_box == to,
+    )
+}
+
+// Turns a click on the board into a route: a click on a box picks it, a click
+// on a free cell walks there or, with a box picked, pushes the box there
+pub fn update_route(world: &mut World) {
+    let clicked = match world.write_resource::<Route>().clicked.take() {
+        Some(clicked) => clicked,
+        None => return,
+    };
+    let level_map = map::current_level_map(world);
+    if clicked.0 >= level_map.width || clicked.1 >= level_map.height {
+        return;
+    }
+
+    let mut route = world.write_resource::<Route>();
+    if level_map.tile(clicked.0, clicked.1).box_colour.is_some() {
+        route.selected = if route.selected == Some(clicked) { None } else { Some(clicked) };
+        return;
+    }
+
+    let steps = match route.selected.take() {
+        Some(the_box) => push_path(&level_map, the_box, clicked),
+        None => walk_path(&level_map, clicked),
+    };
+    match steps {
+        Some(steps) => route.steps = steps.into(),
+        None => world.write_resource::<EventQueue>().events.push(Event::PlayerHitObstacle {}),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::test_level;
+    use Direction::{Down, Left, Right, Up};
+
+    #[test]
+    fn walks_around_walls() {
+        let level_map = test_level("#######\n#@ #  #\n#  #  #\n#     #\n#######");
+        let path = walk_path(&level_map, (4, 1)).unwrap();
+        assert_eq!(path.len(), 7);
+        assert_eq!(path.iter().filter(|direction| **direction == Up).count(), 2);
+    }
+
+    #[test]
+    fn cannot_walk_to_walls_boxes_or_closed_off_cells() {
+        let level_map = test_level("#######\n#@ $# #\n#######");
+        assert_eq!(walk_path(&level_map, (4, 1)), None);
+        assert_eq!(walk_path(&level_map, (3, 1)), None);
+        assert_eq!(walk_path(&level_map, (5, 1)), None);
+        assert_eq!(walk_path(&level_map, (1, 1)), Some(vec![]));
+    }
+
+    #[test]
+    fn pushes_straight_on() {
+        let level_map = test_level("######\n#@$  #\n######");
+        assert_eq!(push_path(&level_map, (2, 1), (4, 1)), Some(vec![Right, Right]));
+    }
+
+    #[test]
+    fn walls_block_pushes() {
+        let level_map = test_level("######\n#@$  #\n######");
+        assert_eq!(push_path(&level_map, (2, 1), (2, 0)), None);
+        // The player cannot get past the box to push it back
+        assert_eq!(push_path(&level_map, (2, 1), (1, 1)), None);
+    }
+
+    #[test]
+    fn other_boxes_block_pushes() {
+        let level_map = test_level("#######\n#@$$  #\n#######");
+        assert_eq!(push_path(&level_map, (2, 1), (4, 1)), None);
+    }
+
+    #[test]
+    fn player_walks_round_to_push() {
+        let level_map = test_level("#######\n#     #\n# @$  #\n#     #\n#######");
+        assert_eq!(push_path(&level_map, (3, 2), (3, 3)), Some(vec![Up, Right, Down]));
+        let path = push_path(&level_map, (3, 2), (2, 2)).unwrap();
+        assert_eq!(path.last(), Some(&Left));
+        assert_eq!(path.len(), 5);
+    }
+}
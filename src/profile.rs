@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SaveError;
+
+pub const PROFILE_FILE: &str = "./profile.toml";
+
+// Bumped whenever the layout below changes, like the save format
+pub const PROFILE_VERSION: u32 = 1;
+
+// Best results for a solved level. Moves and pushes are the best ever seen,
+// possibly from different solutions; the LURD string is the one with the fewest moves.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LevelScore {
+    pub moves: u32,
+    pub pushes: u32,
+    // Fastest win in seconds
+    pub seconds: f64,
+    pub solution: String,
+}
+
+impl LevelScore {
+    pub fn new(moves: u32, pushes: u32, time: Duration, solution: String) -> Self {
+        Self { moves, pushes, seconds: time.as_secs_f64(), solution }
+    }
+}
+
+// Solved levels of every collection, by collection name and level title
+#[derive(Serialize, Deserialize)]
+pub struct Profile {
+    pub version: u32,
+    pub collections: BTreeMap<String, BTreeMap<String, LevelScore>>,
+    // Set when a score changed and the profile needs writing out
+    #[serde(skip)]
+    pub changed: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self { version: PROFILE_VERSION, collections: BTreeMap::new(), changed: false }
+    }
+}
+
+impl Profile {
+    pub fn score(&self, collection: &str, title: &str) -> Option<&LevelScore> {
+        self.collections.get(collection).and_then(|levels| levels.get(title))
+    }
+
+    // Keeps whatever is better of the known score and the new one
+    pub fn record(&mut self, collection: &str, title: &str, score: LevelScore) {
+        let levels = self.collections.entry(collection.to_string()).or_default();
+        let best = match levels.get(title) {
+            None => score,
+            Some(known) => LevelScore {
+                moves: known.moves.min(score.moves),
+                pushes: known.pushes.min(score.pushes),
+                seconds: known.seconds.min(score.seconds),
+                solution: if score.moves < known.moves { score.solution } else { known.solution.clone() },
+            },
+        };
+        if levels.get(title) != Some(&best) {
+            levels.insert(title.to_string(), best);
+            self.changed = true;
+        }
+    }
+}
+
+// A missing profile is a fresh one, anything else wrong with it is reported
+pub fn load_profile(file: &str) -> Result<Profile, SaveError> {
+    let source = match fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(_) => return Ok(Profile::default()),
+    };
+    let format_error = |error: toml::de::Error| SaveError::Format { file: file.to_string(), message: error.to_string() };
+
+    let value = toml::from_str::<toml::Value>(&source).map_err(format_error)?;
+    let version = value.get("version").and_then(|version| version.as_integer()).unwrap_or(0);
+    if version != PROFILE_VERSION as i64 {
+        return Err(SaveError::Version { file: file.to_string(), version: version as u32 });
+    }
+
+    toml::from_str::<Profile>(&source).map_err(format_error)
+}
+
+pub fn save_profile(profile: &Profile, file: &str) -> Result<(), SaveError> {
+    let source = toml::to_string(profile).map_err(|error| SaveError::Format {
+        file: file.to_string(),
+        message: error.to_string(),
+    })?;
+    fs::write(file, source).map_err(|error| SaveError::Io { file: file.to_string(), message: error.to_string() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(moves: u32, pushes: u32, seconds: u64, solution: &str) -> LevelScore {
+        LevelScore::new(moves, pushes, Duration::from_secs(seconds), solution.to_string())
+    }
+
+    #[test]
+    fn first_win_is_kept() {
+        let mut profile = Profile::default();
+        profile.record("default", "Level 1", score(12, 3, 30, "first"));
+        assert_eq!(profile.score("default", "Level 1"), Some(&score(12, 3, 30, "first")));
+        assert!(profile.changed);
+    }
+
+    #[test]
+    fn best_values_are_kept_apart() {
+        let mut profile = Profile::default();
+        profile.record("default", "Level 1", score(12, 3, 30, "first"));
+        profile.record("default", "Level 1", score(14, 2, 40, "fewer pushes"));
+        profile.record("default", "Level 1", score(16, 4, 20, "faster"));
+        assert_eq!(profile.score("default", "Level 1"), Some(&score(12, 2, 20, "first")));
+    }
+
+    #[test]
+    fn fewest_moves_solution_is_kept() {
+        let mut profile = Profile::default();
+        profile.record("default", "Level 1", score(12, 3, 30, "first"));
+        profile.record("default", "Level 1", score(10, 5, 50, "shorter"));
+        let best = profile.score("default", "Level 1").unwrap();
+        assert_eq!((best.moves, best.solution.as_str()), (10, "shorter"));
+
+        // As many moves again is no better
+        profile.record("default", "Level 1", score(10, 5, 50, "as short"));
+        assert_eq!(profile.score("default", "Level 1").unwrap().solution, "shorter");
+    }
+
+    #[test]
+    fn worse_win_changes_nothing() {
+        let mut profile = Profile::default();
+        profile.record("default", "Level 1", score(12, 3, 30, "first"));
+        profile.changed = false;
+        profile.record("default", "Level 1", score(20, 6, 60, "worse"));
+        assert!(!profile.changed);
+        assert_eq!(profile.score("default", "Level 2"), None);
+    }
+}
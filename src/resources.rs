@@ -1,29 +1,45 @@
-use std::{fmt::Display, time::Duration};
+use std::{collections::VecDeque, fmt::Display, time::Duration};
 use std::fmt;
 
-use ggez::event::KeyCode;
+use serde::{Deserialize, Serialize};
 use specs::World;
 
-use crate::audio::AudioStore;
-use crate::events::Event;
+use crate::actions::Action;
+use crate::animation::Animation;
+use crate::bindings::Bindings;
+use crate::camera::Camera;
+use crate::constants::{ROUTE_STEP_INTERVAL, REPLAY_MAX_STEPS_PER_SECOND, REPLAY_MIN_STEPS_PER_SECOND, REPLAY_STEPS_PER_SECOND};
+use crate::collection::Collection;
+use crate::config::GamepadConfig;
+use crate::deadlock::DeadSquares;
+use crate::editor::Editor;
+use crate::errors::LevelError;
+use crate::components::{Direction, Position};
+use crate::map::LevelMap;
+use crate::profile::Profile;
+use crate::screens::Screens;
+use crate::events::{EntityId, Event};
+use crate::gamepad::Gamepad;
+use crate::hints::HintSearch;
+use crate::layout::Layout;
+use crate::skins::Skins;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum GameplayState {
+    #[default]
     Playing,
-    Won
-}
-
-impl Default for GameplayState {
-    fn default() -> Self {
-        Self::Playing
-    }
+    Won,
+    // Some box can never reach a spot any more, undo or restart
+    Deadlocked,
 }
 
 impl Display for GameplayState {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.write_str(match self {
             GameplayState::Playing => "Playing",
-            GameplayState::Won => "Won"
+            GameplayState::Won => "Won",
+            GameplayState::Deadlocked => "Deadlocked",
         })?;
         Ok(())
     }
@@ -38,12 +54,293 @@ pub struct EventQueue {
 pub struct Gameplay {
     pub state: GameplayState,
     pub moves_count: u32,
-    pub level: u8,
+    pub level: usize,
+    // Time at which the level was started, to tell how long winning it took
+    pub started_at: Duration,
+    // Time at which the level was won, used to advance to the next one
+    pub won_at: Option<Duration>,
+    // Time spent on the level before it was saved, when it was resumed
+    pub earlier: Duration,
+    // Set while a saved game replays its moves, its win was already recorded
+    pub restoring: bool,
+}
+
+impl Gameplay {
+    // How long the level has been played, up to the win
+    pub fn elapsed(&self, now: Duration) -> Duration {
+        self.won_at.unwrap_or(now).saturating_sub(self.started_at) + self.earlier
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LevelChange {
+    Restart,
+    Next,
+    Previous,
+    // Picked from the level list, counting from 0
+    Select(usize),
+}
+
+#[derive(Default)]
+pub struct LevelQueue {
+    pub change: Option<LevelChange>,
+}
+
+// Why the current level could not be started, shown instead of the board
+#[derive(Default)]
+pub struct LevelLoadError {
+    pub error: Option<LevelError>,
 }
 
 #[derive(Default)]
 pub struct InputQueue {
-    pub keys_pressed: Vec<KeyCode>,
+    pub actions: Vec<Action>,
+}
+
+impl InputQueue {
+    // Takes the oldest action, keys are handled in the order they were pressed
+    pub fn pop_front(&mut self) -> Option<Action> {
+        if self.actions.is_empty() {
+            None
+        } else {
+            Some(self.actions.remove(0))
+        }
+    }
+}
+
+// A single entity position change, stored so it can be reverted or re-applied exactly
+#[derive(Clone, Copy)]
+pub struct EntityMove {
+    pub id: EntityId,
+    pub from: Position,
+    pub to: Position,
+}
+
+// Everything that moved during one player step: the player and every box pushed along
+#[derive(Clone)]
+pub struct MoveRecord {
+    pub direction: Direction,
+    pub moves: Vec<EntityMove>,
+}
+
+impl MoveRecord {
+    // Anything besides the player moving means a box was pushed
+    pub fn is_push(&self) -> bool {
+        self.moves.len() > 1
+    }
+}
+
+#[derive(Default)]
+pub struct MoveHistory {
+    pub undo_stack: Vec<MoveRecord>,
+    pub redo_stack: Vec<MoveRecord>,
+}
+
+impl MoveHistory {
+    // A fresh move invalidates everything that was undone before it
+    pub fn record(&mut self, record: MoveRecord) {
+        self.undo_stack.push(record);
+        self.redo_stack.clear();
+    }
+
+    // The moves made so far in LURD notation, undone moves left out
+    pub fn lurd(&self) -> String {
+        self.undo_stack
+            .iter()
+            .map(|record| record.direction.to_lurd(record.is_push()))
+            .collect()
+    }
+}
+
+// Path being walked after a click on the board, one step at a time
+#[derive(Default)]
+pub struct Route {
+    // Cell clicked, waiting for a path to be found
+    pub clicked: Option<(u8, u8)>,
+    // Box picked to be pushed to the next cell clicked
+    pub selected: Option<(u8, u8)>,
+    pub steps: VecDeque<Direction>,
+    pub last_step: Option<Duration>,
+}
+
+impl Route {
+    pub fn clear(&mut self) {
+        self.selected = None;
+        self.steps.clear();
+    }
+
+    // The next step once it is due, the first one goes right away
+    pub fn next_step(&mut self, now: Duration) -> Option<Direction> {
+        if self.steps.is_empty() {
+            self.last_step = None;
+            return None;
+        }
+        if self.last_step.is_some_and(|last_step| now < last_step + ROUTE_STEP_INTERVAL) {
+            return None;
+        }
+        self.last_step = Some(now);
+        self.steps.pop_front()
+    }
+}
+
+// Steps of a LURD string fed back through the input system one at a time
+pub struct Replay {
+    pub steps: Vec<Direction>,
+    pub position: usize,
+    pub steps_per_second: f32,
+    pub paused: bool,
+    // Set to take a single step while paused
+    pub step_requested: bool,
+    pub last_step: Duration,
+}
+
+impl Default for Replay {
+    fn default() -> Self {
+        Self {
+            steps: Vec::new(),
+            position: 0,
+            steps_per_second: REPLAY_STEPS_PER_SECOND,
+            paused: false,
+            step_requested: false,
+            last_step: Duration::default(),
+        }
+    }
+}
+
+impl Replay {
+    // Reads a LURD string, run lengths such as "3l" are expanded and anything
+    // else that is not a step (spaces, line breaks) is skipped
+    pub fn new(lurd: &str) -> Self {
+        let mut steps = Vec::new();
+        let mut count = String::new();
+        for c in lurd.chars() {
+            if c.is_ascii_digit() {
+                count.push(c);
+                continue;
+            }
+            if let Some((direction, _push)) = Direction::from_lurd(c) {
+                let repeat = count.parse::<usize>().unwrap_or(1);
+                steps.extend(std::iter::repeat_n(direction, repeat));
+            }
+            count.clear();
+        }
+        Self { steps, ..Self::default() }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.position < self.steps.len()
+    }
+
+    pub fn stop(&mut self) {
+        self.steps.clear();
+        self.position = 0;
+    }
+
+    pub fn change_speed(&mut self, factor: f32) {
+        self.steps_per_second = (self.steps_per_second * factor)
+            .clamp(REPLAY_MIN_STEPS_PER_SECOND, REPLAY_MAX_STEPS_PER_SECOND);
+    }
+
+    // The next step once it is due at the current playback speed
+    pub fn next_step(&mut self, now: Duration) -> Option<Direction> {
+        if !self.is_active() {
+            return None;
+        }
+        let interval = Duration::from_secs_f32(1.0 / self.steps_per_second);
+        let due = self.step_requested || (!self.paused && now >= self.last_step + interval);
+        if !due {
+            return None;
+        }
+
+        self.step_requested = false;
+        self.last_step = now;
+        self.position += 1;
+        Some(self.steps[self.position - 1])
+    }
+}
+
+impl Display for Replay {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        if !self.is_active() {
+            return Ok(());
+        }
+        write!(fmt, "Replay {}/{}", self.position, self.steps.len())?;
+        if self.paused {
+            fmt.write_str(" paused")
+        } else {
+            write!(fmt, " x{}", self.steps_per_second / REPLAY_STEPS_PER_SECOND)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PushRule {
+    // Standard Sokoban: the player pushes at most one box at a time
+    #[default]
+    Single,
+    // Every box in a row in front of the player moves along
+    Chain,
+}
+
+#[derive(Default)]
+pub struct Ruleset {
+    pub push_rule: PushRule,
+}
+
+// The level as it was loaded, before anything moved
+#[derive(Default)]
+pub struct CurrentMap {
+    pub map: LevelMap,
+}
+
+// Maps loaded so far, the front end reads the images of the new tiles whenever
+// this goes up
+#[derive(Default)]
+pub struct MapLoads {
+    pub count: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum HintStatus {
+    #[default]
+    Hidden,
+    Thinking,
+    // Next move of a solution, the target is the tile the player should step on
+    Move { direction: Direction, push: bool, x: u8, y: u8 },
+    NoSolution,
+    GaveUp,
+}
+
+impl Display for HintStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HintStatus::Hidden => Ok(()),
+            HintStatus::Thinking => fmt.write_str("Hint: thinking..."),
+            HintStatus::Move { direction, push: true, .. } => write!(fmt, "Hint: push {}", direction),
+            HintStatus::Move { direction, push: false, .. } => write!(fmt, "Hint: {}", direction),
+            HintStatus::NoSolution => fmt.write_str("No solution from here"),
+            HintStatus::GaveUp => fmt.write_str("Hint: too hard"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Hint {
+    pub requested: bool,
+    pub status: HintStatus,
+    // Player and box positions the hint was computed for
+    pub layout: Vec<(u8, u8)>,
+    // Solver running in the background so frames keep coming, stopped
+    // when the hint is dropped, see HintSearch
+    pub search: Option<HintSearch>,
+}
+
+// Size in tiles of the level being played
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct MapSize {
+    pub width: u8,
+    pub height: u8,
 }
 
 #[derive(Default)]
@@ -57,5 +354,84 @@ pub fn register_resources(world: &mut World) {
     world.insert(Gameplay::default());
     world.insert(Time::default());
     world.insert(EventQueue::default());
-    world.insert(AudioStore::default());
-}
\ No newline at end of file
+    world.insert(MoveHistory::default());
+    world.insert(LevelQueue::default());
+    world.insert(Collection::default());
+    world.insert(LevelLoadError::default());
+    world.insert(MapSize::default());
+    world.insert(Ruleset::default());
+    world.insert(CurrentMap::default());
+    world.insert(MapLoads::default());
+    world.insert(Hint::default());
+    world.insert(DeadSquares::default());
+    world.insert(Replay::default());
+    world.insert(Route::default());
+    world.insert(Profile::default());
+    world.insert(Screens::default());
+    world.insert(Editor::default());
+    world.insert(Bindings::default());
+    world.insert(Animation::default());
+    world.insert(Camera::default());
+    world.insert(Layout::default());
+    world.insert(Skins::default());
+    world.insert(Gamepad::new(&GamepadConfig::default()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::test_simulation;
+
+    #[test]
+    fn lurd_round_trip() {
+        let mut simulation = test_simulation("######\n#@$ .#\n#    #\n######");
+        for direction in [Direction::Right, Direction::Down, Direction::Right, Direction::Left] {
+            simulation.step(Action::Move(direction));
+        }
+        assert_eq!(simulation.lurd(), "Rdrl");
+        assert_eq!(
+            Replay::new(&simulation.lurd()).steps,
+            [Direction::Right, Direction::Down, Direction::Right, Direction::Left]
+        );
+
+        // Undone moves are not part of the solution
+        simulation.step(Action::Undo);
+        assert_eq!(simulation.lurd(), "Rdr");
+    }
+
+    #[test]
+    fn replay_run_lengths() {
+        let replay = Replay::new("3l2U\nr");
+        assert_eq!(
+            replay.steps,
+            [Direction::Left, Direction::Left, Direction::Left, Direction::Up, Direction::Up, Direction::Right]
+        );
+    }
+
+    #[test]
+    fn replay_skips_what_is_not_a_step() {
+        // A count only applies to the step right after it
+        assert_eq!(Replay::new("x3?l").steps, [Direction::Left]);
+        assert!(Replay::new("12 #").steps.is_empty());
+        assert!(!Replay::new("").is_active());
+    }
+
+    #[test]
+    fn replay_pause_and_step() {
+        let mut replay = Replay::new("rrr");
+        let interval = Duration::from_secs_f32(1.0 / REPLAY_STEPS_PER_SECOND);
+        assert_eq!(replay.next_step(Duration::ZERO), None);
+        assert_eq!(replay.next_step(interval), Some(Direction::Right));
+
+        replay.paused = true;
+        assert_eq!(replay.next_step(interval * 4), None);
+        replay.step_requested = true;
+        assert_eq!(replay.next_step(interval * 4), Some(Direction::Right));
+        assert_eq!(replay.next_step(interval * 8), None);
+
+        replay.paused = false;
+        assert_eq!(replay.next_step(interval * 8), Some(Direction::Right));
+        assert!(!replay.is_active());
+        assert_eq!(replay.next_step(interval * 16), None);
+    }
+}
@@ -0,0 +1,331 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use specs::{Join, World, WorldExt};
+
+use crate::actions::Action;
+use crate::collection::{self, Collection};
+use crate::components::{Box, BoxColour, Player, Position};
+use crate::errors::SaveError;
+use crate::events::{EntityMoved, Event};
+use crate::resources::{
+    EventQueue, Gameplay, GameplayState, LevelLoadError, MoveHistory, PushRule, Replay, Ruleset, Time,
+};
+use crate::simulation::Simulation;
+
+pub const SAVE_FILE: &str = "./save.toml";
+
+// Bumped whenever the layout below changes, migrate brings older saves up to it
+pub const SAVE_VERSION: u32 = 2;
+
+// Which level the save belongs to, the title tells if the level was edited since
+#[derive(Serialize, Deserialize)]
+pub struct SavedLevel {
+    pub collection: String,
+    pub number: usize,
+    pub title: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedBox {
+    pub x: u8,
+    pub y: u8,
+    pub colour: BoxColour,
+}
+
+// Entity ids change from run to run, so the history is kept as the LURD steps
+// that rebuild it and positions are kept in plain map coordinates
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub push_rule: PushRule,
+    pub state: GameplayState,
+    pub moves_count: u32,
+    // Time spent on the level so far
+    pub seconds: f64,
+    pub undo: String,
+    // Undone moves, the next one to redo first
+    pub redo: String,
+    pub player: (u8, u8),
+    pub level: SavedLevel,
+    pub boxes: Vec<SavedBox>,
+}
+
+// Where the player and the boxes are, boxes in reading order
+fn current_layout(world: &World) -> (Option<(u8, u8)>, Vec<SavedBox>) {
+    let positions = world.read_storage::<Position>();
+    let player = (&positions, &world.read_storage::<Player>())
+        .join()
+        .map(|(position, _)| (position.x, position.y))
+        .next();
+    let mut boxes = (&positions, &world.read_storage::<Box>())
+        .join()
+        .map(|(position, the_box)| SavedBox { x: position.x, y: position.y, colour: the_box.colour })
+        .collect::<Vec<_>>();
+    boxes.sort_by_key(|saved| (saved.y, saved.x));
+    (player, boxes)
+}
+
+// Writes the level being played to the save file. Nothing is written when the
+// level could not be started, there is nothing to resume then.
+pub fn save_game(world: &World, file: &str) -> Result<(), SaveError> {
+    if world.read_resource::<LevelLoadError>().error.is_some() {
+        return Ok(());
+    }
+    let (player, boxes) = match current_layout(world) {
+        (Some(player), boxes) => (player, boxes),
+        (None, _) => return Ok(()),
+    };
+
+    let gameplay = world.read_resource::<Gameplay>();
+    let history = world.read_resource::<MoveHistory>();
+    let collection = world.read_resource::<Collection>();
+    let now = world.read_resource::<Time>().delta;
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        push_rule: world.read_resource::<Ruleset>().push_rule,
+        state: gameplay.state,
+        moves_count: gameplay.moves_count,
+        seconds: gameplay.elapsed(now).as_secs_f64(),
+        undo: history.lurd(),
+        redo: history
+            .redo_stack
+            .iter()
+            .rev()
+            .map(|record| record.direction.to_lurd(record.is_push()))
+            .collect(),
+        player,
+        level: SavedLevel {
+            collection: collection.name.clone(),
+            number: gameplay.level,
+            title: collection.levels.get(gameplay.level).map(|level| level.title.clone()).unwrap_or_default(),
+        },
+        boxes,
+    };
+
+    let source = toml::to_string(&save).map_err(|error| SaveError::Format {
+        file: file.to_string(),
+        message: error.to_string(),
+    })?;
+    fs::write(file, source).map_err(|error| SaveError::Io { file: file.to_string(), message: error.to_string() })
+}
+
+pub fn save_exists(file: &str) -> bool {
+    Path::new(file).is_file()
+}
+
+// Brings a save of the given version up to the current layout, one version
+// at a time
+fn migrate(mut value: toml::Value, from_version: u32) -> toml::Value {
+    match from_version {
+        // Version 1 did not keep the time spent, the clock starts over
+        1 => {
+            if let Some(table) = value.as_table_mut() {
+                table.insert("seconds".to_string(), toml::Value::Float(0.0));
+                table.insert("version".to_string(), toml::Value::Integer(2));
+            }
+            migrate(value, 2)
+        }
+        _ => value,
+    }
+}
+
+fn read_save(file: &str) -> Result<SaveGame, SaveError> {
+    let source = fs::read_to_string(file)
+        .map_err(|error| SaveError::Io { file: file.to_string(), message: error.to_string() })?;
+    let format_error = |error: toml::de::Error| SaveError::Format { file: file.to_string(), message: error.to_string() };
+
+    // Look at the version alone first, an older save is migrated and a newer
+    // one may not parse as this one
+    let value = toml::from_str::<toml::Value>(&source).map_err(format_error)?;
+    let version = value.get("version").and_then(|version| version.as_integer()).unwrap_or(0);
+    if version < 1 || version > SAVE_VERSION as i64 {
+        return Err(SaveError::Version { file: file.to_string(), version: version as u32 });
+    }
+
+    migrate(value, version as u32).try_into::<SaveGame>().map_err(format_error)
+}
+
+// Puts the player and the boxes straight where the save says, dropping the
+// history, for when replaying the saved moves does not lead there
+fn place_entities(simulation: &mut Simulation, save: &SaveGame) {
+    {
+        let world = &simulation.world;
+        let entities = world.entities();
+        let mut positions = world.write_storage::<Position>();
+        for (position, _player) in (&mut positions, &world.read_storage::<Player>()).join() {
+            position.x = save.player.0;
+            position.y = save.player.1;
+        }
+
+        let mut saved_boxes = save.boxes.clone();
+        let mut events = world.write_resource::<EventQueue>();
+        for (entity, position, the_box) in (&entities, &mut positions, &world.read_storage::<Box>()).join() {
+            if let Some(index) = saved_boxes.iter().position(|saved| saved.colour == the_box.colour) {
+                let saved = saved_boxes.remove(index);
+                position.x = saved.x;
+                position.y = saved.y;
+            }
+            // Lets the deadlock and win checks look at the new layout
+            events.events.push(Event::EntityMoved(EntityMoved { id: entity.id() }));
+        }
+        *world.write_resource::<MoveHistory>() = MoveHistory::default();
+    }
+    simulation.update();
+}
+
+// Starts the saved level in the simulation and brings it back to where it was
+// left. Returns what did not come back exactly, for the front end to report.
+pub fn load_game(simulation: &mut Simulation, file: &str) -> Result<Vec<String>, SaveError> {
+    let save = read_save(file)?;
+    let collection = collection::load_collection(&save.level.collection).map_err(SaveError::Level)?;
+    match collection.levels.get(save.level.number) {
+        Some(level) if level.title == save.level.title => (),
+        _ => return Err(SaveError::LevelChanged { title: save.level.title.clone() }),
+    }
+
+    simulation.world.write_resource::<Ruleset>().push_rule = save.push_rule;
+    simulation.start(collection, save.level.number);
+    if let Some(error) = simulation.world.read_resource::<LevelLoadError>().error.clone() {
+        return Err(SaveError::Level(error));
+    }
+
+    // Replaying every move, then undoing the redo ones, rebuilds the history exactly
+    simulation.world.write_resource::<Gameplay>().restoring = true;
+    let redo_steps = Replay::new(&save.redo).steps;
+    for direction in Replay::new(&save.undo).steps.into_iter().chain(redo_steps.iter().copied()) {
+        simulation.step(Action::Move(direction));
+    }
+    for _ in redo_steps.iter() {
+        simulation.step(Action::Undo);
+    }
+
+    // The moves count follows the history, unless there is none left to follow
+    let mut warnings = Vec::new();
+    let mut moves_count = simulation.world.read_resource::<MoveHistory>().undo_stack.len() as u32;
+    if current_layout(&simulation.world) != (Some(save.player), save.boxes.clone()) {
+        warnings.push("Saved moves do not lead to the saved position, restoring positions only".to_string());
+        place_entities(simulation, &save);
+        moves_count = save.moves_count;
+    }
+
+    // The time already spent is added on to the time played from now on
+    let now = simulation.world.read_resource::<Time>().delta;
+    let mut gameplay = simulation.world.write_resource::<Gameplay>();
+    gameplay.state = save.state;
+    gameplay.moves_count = moves_count;
+    gameplay.started_at = gameplay.won_at.unwrap_or(now);
+    gameplay.earlier = Duration::try_from_secs_f64(save.seconds).unwrap_or_default();
+    gameplay.restoring = false;
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Direction;
+    use crate::profile::Profile;
+
+    // A file of its own under the temporary directory, for each test
+    fn temp_file(name: &str) -> String {
+        std::env::temp_dir().join(format!("sokoban-{}-{}.toml", name, std::process::id())).display().to_string()
+    }
+
+    // Level 1 of the default collection after one step to the left
+    const VERSION_1_SAVE: &str = r#"
+version = 1
+push_rule = "single"
+state = "playing"
+moves_count = 1
+undo = "l"
+redo = ""
+player = [2, 2]
+boxes = [{ x = 3, y = 3, colour = "blue" }, { x = 4, y = 5, colour = "red" }]
+
+[level]
+collection = "default"
+number = 1
+title = "Level 1"
+"#;
+
+    #[test]
+    fn version_1_is_migrated() {
+        let file = temp_file("version-1");
+        fs::write(&file, VERSION_1_SAVE).unwrap();
+        let save = read_save(&file);
+        let mut simulation = Simulation::default();
+        let warnings = load_game(&mut simulation, &file);
+        fs::remove_file(&file).unwrap();
+
+        let save = save.unwrap();
+        assert_eq!(save.version, SAVE_VERSION);
+        assert_eq!(save.seconds, 0.0);
+        assert_eq!(warnings.unwrap(), Vec::<String>::new());
+        assert_eq!(simulation.level(), 1);
+        assert_eq!(simulation.lurd(), "l");
+        assert_eq!(simulation.moves_count(), 1);
+    }
+
+    #[test]
+    fn newer_version_is_refused() {
+        let file = temp_file("newer");
+        fs::write(&file, VERSION_1_SAVE.replace("version = 1", "version = 99")).unwrap();
+        let save = read_save(&file);
+        fs::remove_file(&file).unwrap();
+        assert!(matches!(save, Err(SaveError::Version { version: 99, .. })));
+    }
+
+    #[test]
+    fn save_and_resume() {
+        let mut simulation = Simulation::new("default", 1).unwrap();
+        simulation.world.write_resource::<Time>().delta = Duration::from_secs(10);
+        simulation.world.write_resource::<Gameplay>().started_at = Duration::from_secs(4);
+        for direction in [Direction::Left, Direction::Left, Direction::Down, Direction::Down] {
+            simulation.step(Action::Move(direction));
+        }
+        simulation.step(Action::Undo);
+
+        let file = temp_file("round-trip");
+        save_game(&simulation.world, &file).unwrap();
+        let mut resumed = Simulation::default();
+        let warnings = load_game(&mut resumed, &file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(warnings.unwrap(), Vec::<String>::new());
+        assert_eq!(resumed.level(), 1);
+        assert_eq!(resumed.lurd(), "lld");
+        assert_eq!(resumed.moves_count(), simulation.moves_count());
+        assert_eq!(current_layout(&resumed.world), current_layout(&simulation.world));
+        // The clock of the resumed game is left alone, the time spent carries over
+        let now = resumed.world.read_resource::<Time>().delta;
+        assert_eq!(now, Duration::ZERO);
+        assert_eq!(resumed.world.read_resource::<Gameplay>().elapsed(now), Duration::from_secs(6));
+
+        // The undone move is still there to redo
+        resumed.step(Action::Redo);
+        assert_eq!(resumed.lurd(), "lldd");
+    }
+
+    #[test]
+    fn resumed_win_is_not_recorded_again() {
+        let mut simulation = Simulation::new("tutorial", 0).unwrap();
+        simulation.world.write_resource::<Time>().delta = Duration::from_secs(3);
+        simulation.step(Action::Move(Direction::Right));
+        assert_eq!(simulation.state(), GameplayState::Won);
+
+        let file = temp_file("won");
+        save_game(&simulation.world, &file).unwrap();
+        let mut resumed = Simulation::default();
+        let warnings = load_game(&mut resumed, &file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(warnings.unwrap(), Vec::<String>::new());
+        assert_eq!((resumed.state(), resumed.moves_count()), (GameplayState::Won, 1));
+        assert!(resumed.world.read_resource::<Profile>().collections.is_empty());
+        assert!(!resumed.world.read_resource::<Gameplay>().restoring);
+        let now = resumed.world.read_resource::<Time>().delta;
+        assert_eq!(resumed.world.read_resource::<Gameplay>().elapsed(now), Duration::from_secs(3));
+    }
+}
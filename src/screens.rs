@@ -0,0 +1,99 @@
+use std::fmt;
+use std::fmt::Display;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuItem {
+    Play,
+    Levels,
+    Editor,
+    Controls,
+    Quit,
+    Resume,
+    Restart,
+    Title,
+}
+
+impl Display for MenuItem {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            MenuItem::Play => "Play",
+            MenuItem::Levels => "Levels",
+            MenuItem::Editor => "Level editor",
+            MenuItem::Controls => "Controls",
+            MenuItem::Quit => "Quit",
+            MenuItem::Resume => "Resume",
+            MenuItem::Restart => "Restart level",
+            MenuItem::Title => "Back to title",
+        })
+    }
+}
+
+pub const TITLE_MENU: [MenuItem; 5] =
+    [MenuItem::Play, MenuItem::Levels, MenuItem::Editor, MenuItem::Controls, MenuItem::Quit];
+pub const PAUSE_MENU: [MenuItem; 4] = [MenuItem::Resume, MenuItem::Restart, MenuItem::Levels, MenuItem::Title];
+
+// What is shown and gets the input. Menus remember the highlighted entry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Screen {
+    Title { selected: usize },
+    LevelSelect { selected: usize },
+    Playing,
+    // Shown over the board
+    Paused { selected: usize },
+    WonSummary,
+    // Painting a level, see the editor module
+    Editor,
+    // Key bindings, waiting when the next key pressed goes to the selected action
+    Controls { selected: usize, waiting: bool },
+}
+
+impl Screen {
+    pub fn menu(self) -> &'static [MenuItem] {
+        match self {
+            Screen::Title { .. } => &TITLE_MENU,
+            Screen::Paused { .. } => &PAUSE_MENU,
+            _ => &[],
+        }
+    }
+
+    // Whether the board stays visible under this screen
+    pub fn shows_board(self) -> bool {
+        matches!(self, Screen::Playing | Screen::Paused { .. } | Screen::WonSummary | Screen::Editor)
+    }
+}
+
+// Screens on top of each other, the last one is active. Headless runs stay on
+// the board, the game itself starts on the title screen.
+pub struct Screens {
+    pub stack: Vec<Screen>,
+    // Set from the title menu, the front end closes the window
+    pub quit: bool,
+}
+
+impl Default for Screens {
+    fn default() -> Self {
+        Self { stack: vec![Screen::Playing], quit: false }
+    }
+}
+
+impl Screens {
+    pub fn current(&self) -> Screen {
+        self.stack.last().copied().unwrap_or(Screen::Playing)
+    }
+
+    pub fn push(&mut self, screen: Screen) {
+        self.stack.push(screen);
+    }
+
+    // The bottom screen is never taken away
+    pub fn pop(&mut self) {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+        }
+    }
+
+    pub fn replace(&mut self, screen: Screen) {
+        self.stack.pop();
+        self.stack.push(screen);
+    }
+}
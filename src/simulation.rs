@@ -0,0 +1,176 @@
+use specs::{RunNow, World, WorldExt};
+
+use crate::actions::Action;
+use crate::camera;
+use crate::collection::{self, Collection};
+#[cfg(test)]
+use crate::collection::LevelInfo;
+use crate::components;
+use crate::editor;
+use crate::errors::LevelError;
+use crate::events::Event;
+use crate::gamepad;
+use crate::hints;
+use crate::layout;
+use crate::levels;
+use crate::map;
+use crate::pathfinding;
+use crate::resources::{self, EventQueue, Gameplay, GameplayState, InputQueue, MoveHistory};
+use crate::screens::{Screen, Screens};
+use crate::systems;
+
+// The game rules without a window or audio device: actions go in, events come
+// out. The ggez front end drives the very same systems once per frame.
+pub struct Simulation {
+    pub world: World,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        let mut world = World::new();
+        components::register_components(&mut world);
+        resources::register_resources(&mut world);
+        Self { world }
+    }
+}
+
+impl Simulation {
+    // Starts the given level (counting from 0) of a collection under resources/levels
+    pub fn new(collection_name: &str, level: usize) -> Result<Self, LevelError> {
+        let collection = collection::load_collection(collection_name)?;
+        if level >= collection.levels.len() {
+            return Err(LevelError::NotFound { name: format!("level {}", level + 1) });
+        }
+
+        let mut simulation = Self::default();
+        simulation.start(collection, level);
+        Ok(simulation)
+    }
+
+    pub fn start(&mut self, collection: Collection, level: usize) {
+        levels::start_collection(&mut self.world, collection, level);
+    }
+
+    // Applies a single action and returns everything that happened because of it
+    pub fn step(&mut self, action: Action) -> Vec<Event> {
+        self.world.write_resource::<InputQueue>().actions.push(action);
+        self.update()
+    }
+
+    // Runs the systems once, the way a frame does, and returns the events fired
+    pub fn update(&mut self) -> Vec<Event> {
+        // Held gamepad directions move like repeated key presses
+        gamepad::update_gamepad(&mut self.world);
+
+        systems::ScreenSystem {}.run_now(&self.world);
+        systems::InputSystem {}.run_now(&self.world);
+
+        // The rules stand still under menus and in the editor
+        if self.world.read_resource::<Screens>().current() == Screen::Playing {
+            systems::DeadlockSystem {}.run_now(&self.world);
+            systems::GameplayStateSystem {}.run_now(&self.world);
+        }
+
+        // The event system may fire follow-up events, see those through too
+        let mut fired = Vec::new();
+        while !self.world.read_resource::<EventQueue>().events.is_empty() {
+            fired.extend(self.world.read_resource::<EventQueue>().events.iter().cloned());
+            systems::EventSystem {}.run_now(&self.world);
+        }
+
+        // Restart or switch level if requested
+        levels::update_level(&mut self.world);
+
+        // Paint the level being edited
+        editor::update_editor(&mut self.world);
+
+        // Find the way to where the board was clicked
+        pathfinding::update_route(&mut self.world);
+
+        // Start or collect the solver behind the hint action
+        hints::update_hint(&mut self.world);
+
+        // Fit the board and the HUD to the window and the level
+        layout::update_layout(&mut self.world);
+
+        // Look at where the player is now
+        camera::update_camera(&mut self.world);
+
+        fired
+    }
+
+    pub fn state(&self) -> GameplayState {
+        self.world.read_resource::<Gameplay>().state
+    }
+
+    pub fn level(&self) -> usize {
+        self.world.read_resource::<Gameplay>().level
+    }
+
+    // Layout of the level with boxes and player where they are now
+    pub fn level_map(&self) -> map::LevelMap {
+        map::current_level_map(&self.world)
+    }
+
+    pub fn moves_count(&self) -> u32 {
+        self.world.read_resource::<Gameplay>().moves_count
+    }
+
+    // The moves made so far in LURD notation
+    pub fn lurd(&self) -> String {
+        self.world.read_resource::<MoveHistory>().lurd()
+    }
+}
+
+// Plays the given XSB board as the only level of a collection, for tests
+#[cfg(test)]
+pub fn test_simulation(xsb: &str) -> Simulation {
+    let level = LevelInfo::new("Test".to_string(), Ok(map::test_level(xsb)));
+    let mut simulation = Simulation::default();
+    simulation.start(Collection { levels: vec![level], ..Collection::default() }, 0);
+    simulation
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::Direction;
+    use crate::events::BoxPlacedOnSpot;
+
+    #[test]
+    fn step_moves_the_player() {
+        let mut simulation = test_simulation("######\n#@ $.#\n######");
+        let events = simulation.step(Action::Move(Direction::Right));
+        assert!(matches!(events.as_slice(), [Event::EntityMoved(_)]));
+        assert_eq!(simulation.moves_count(), 1);
+        assert_eq!(simulation.lurd(), "r");
+        assert_eq!(simulation.state(), GameplayState::Playing);
+    }
+
+    #[test]
+    fn step_into_a_wall() {
+        let mut simulation = test_simulation("######\n#@ $.#\n######");
+        let events = simulation.step(Action::Move(Direction::Up));
+        assert!(matches!(events.as_slice(), [Event::PlayerHitObstacle]));
+        assert_eq!(simulation.moves_count(), 0);
+        assert_eq!(simulation.lurd(), "");
+    }
+
+    #[test]
+    fn step_onto_the_last_spot_wins() {
+        let mut simulation = test_simulation("#####\n#@$.#\n#####");
+        let events = simulation.step(Action::Move(Direction::Right));
+        assert!(events.iter().any(|event| matches!(event, Event::PlayerWon)));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::BoxPlacedOnSpot(BoxPlacedOnSpot { is_correct_spot: true })
+        )));
+        assert_eq!(simulation.state(), GameplayState::Won);
+        assert_eq!(simulation.lurd(), "R");
+    }
+
+    #[test]
+    fn unknown_level_is_not_found() {
+        assert!(matches!(Simulation::new("default", 10_000), Err(LevelError::NotFound { .. })));
+    }
+}
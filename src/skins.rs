@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::actions::Action;
+use crate::components::TileRole;
+use crate::layout::Rect;
+
+pub const SKINS_DIR: &str = "./resources/skins";
+const RESOURCES_DIR: &str = "./resources";
+pub const DEFAULT_SKIN: &str = "classic";
+const MANIFEST_FILE: &str = "skin.toml";
+
+// The skin that ships with the game, for when none can be read
+const BUILT_IN_SKIN: &str = include_str!("../resources/skins/classic/skin.toml");
+
+// Roles every skin has to draw, boxes and spots may come from the colours file
+const REQUIRED_ROLES: [TileRole; 3] = [TileRole::Wall, TileRole::Floor, TileRole::Player];
+
+// One frame of a tile: an image of its own or a [column, row] cell of the sheet
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Frame {
+    Image(String),
+    Cell([u16; 2]),
+}
+
+// What to draw for a frame: the image, and for sheets the part of it in pixels
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sprite {
+    pub image: String,
+    pub source: Option<Rect>,
+}
+
+// A skin as described by the skin.toml of its directory
+#[derive(Clone, Debug, Deserialize)]
+pub struct Skin {
+    // Directory under resources/skins, the name the config file uses
+    #[serde(skip)]
+    pub id: String,
+    pub name: String,
+    // Size of the sheet cells in pixels, tiles are scaled to the board
+    #[serde(default = "default_tile_size")]
+    pub tile_size: u16,
+    pub sheet: Option<String>,
+    // Frames of each tile role, see TileRole::key
+    pub tiles: BTreeMap<String, Vec<Frame>>,
+}
+
+fn default_tile_size() -> u16 {
+    32
+}
+
+impl Skin {
+    fn parse(id: &str, source: &str) -> Result<Self, String> {
+        let mut skin = toml::from_str::<Skin>(source).map_err(|error| error.to_string())?;
+        skin.id = id.to_string();
+        if skin.tile_size == 0 {
+            return Err("tile_size has to be at least 1".to_string());
+        }
+        for role in REQUIRED_ROLES.iter() {
+            if skin.tiles.get(&role.key()).is_none_or(|frames| frames.is_empty()) {
+                return Err(format!("no frames for {}", role.key()));
+            }
+        }
+        let cells = skin.tiles.values().flatten().any(|frame| matches!(frame, Frame::Cell(_)));
+        if cells && skin.sheet.is_none() {
+            return Err("cells given without a sheet".to_string());
+        }
+        Ok(skin)
+    }
+
+    fn built_in() -> Self {
+        Self::parse(DEFAULT_SKIN, BUILT_IN_SKIN).expect("expected built-in skin")
+    }
+
+    // Image paths are relative to the skin directory, or to resources/ when
+    // they start with a slash
+    fn resource_path(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            path.to_string()
+        } else {
+            format!("/skins/{}/{}", self.id, path)
+        }
+    }
+
+    // Makes sure the sheet and every image the skin names are there, so a
+    // broken skin is left out instead of failing when it is drawn
+    fn check_files(&self, resources: &Path) -> Result<(), String> {
+        let images = self.tiles.values().flatten().filter_map(|frame| match frame {
+            Frame::Image(path) => Some(path),
+            Frame::Cell(_) => None,
+        });
+        for path in self.sheet.iter().chain(images) {
+            let file = resources.join(self.resource_path(path).trim_start_matches('/'));
+            if !file.is_file() {
+                return Err(format!("missing image {}", file.display()));
+            }
+        }
+        Ok(())
+    }
+
+    // Frames a role goes through while moving
+    pub fn frame_count(&self, role: TileRole) -> usize {
+        match (self.tiles.get(&role.key()).filter(|frames| !frames.is_empty()), role) {
+            (Some(frames), _) => frames.len(),
+            (None, TileRole::Box(colour)) => colour.info().box_images.len(),
+            (None, _) => 1,
+        }
+    }
+
+    // What to draw for the given frame of a role, frames wrap around. Boxes
+    // and spots the skin leaves out look the way the colours file says.
+    pub fn sprite(&self, role: TileRole, frame: usize) -> Sprite {
+        let frames = self.tiles.get(&role.key()).filter(|frames| !frames.is_empty());
+        let frame = match (frames, role) {
+            (Some(frames), _) => frames[frame % frames.len()].clone(),
+            (None, TileRole::Box(colour)) => {
+                let images = &colour.info().box_images;
+                return Sprite { image: images[frame % images.len()].clone(), source: None };
+            }
+            (None, TileRole::Spot(colour)) => {
+                return Sprite { image: colour.info().spot_image.clone(), source: None };
+            }
+            (None, _) => unreachable!("skins are checked for the required roles"),
+        };
+
+        match frame {
+            Frame::Image(path) => Sprite { image: self.resource_path(&path), source: None },
+            Frame::Cell([column, row]) => {
+                let size = self.tile_size as f32;
+                Sprite {
+                    image: self.resource_path(self.sheet.as_deref().unwrap_or_default()),
+                    source: Some(Rect::new(column as f32 * size, row as f32 * size, size, size)),
+                }
+            }
+        }
+    }
+}
+
+// Reads the skin of a directory under resources/skins
+pub fn load_skin(dir: &Path) -> Result<Skin, String> {
+    let id = dir.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let source = fs::read_to_string(dir.join(MANIFEST_FILE)).map_err(|error| error.to_string())?;
+    let skin = Skin::parse(id, &source)?;
+    skin.check_files(Path::new(RESOURCES_DIR))?;
+    Ok(skin)
+}
+
+// Every skin found and the one in use
+pub struct Skins {
+    pub skins: Vec<Skin>,
+    pub current: usize,
+    // Set when the player switched skins, the front end keeps the choice
+    pub changed: bool,
+}
+
+impl Default for Skins {
+    fn default() -> Self {
+        Self { skins: vec![Skin::built_in()], current: 0, changed: false }
+    }
+}
+
+impl Skins {
+    // Reads every skin directory, skins that cannot be read are left out and
+    // returned with why. Starts with the given skin, the classic one if it is missing.
+    pub fn load(selected: &str) -> (Self, Vec<String>) {
+        let mut dirs = fs::read_dir(SKINS_DIR)
+            .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        dirs.retain(|dir| dir.is_dir());
+        dirs.sort();
+
+        let mut skins = Vec::new();
+        let mut warnings = Vec::new();
+        for dir in dirs {
+            match load_skin(&dir) {
+                Ok(skin) => skins.push(skin),
+                Err(error) => warnings.push(format!("Skin {}: {}", dir.display(), error)),
+            }
+        }
+        if skins.is_empty() {
+            skins.push(Skin::built_in());
+        }
+
+        let find = |id: &str| skins.iter().position(|skin| skin.id == id);
+        let current = find(selected).or_else(|| find(DEFAULT_SKIN)).unwrap_or(0);
+        if !selected.is_empty() && find(selected).is_none() {
+            warnings.push(format!("Skin {} not found", selected));
+        }
+        (Self { skins, current, changed: false }, warnings)
+    }
+
+    pub fn current(&self) -> &Skin {
+        &self.skins[self.current]
+    }
+
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.skins.len();
+        self.changed = true;
+    }
+
+    // Applies the skin switch, false for any other action
+    pub fn apply(&mut self, action: Action) -> bool {
+        if action == Action::NextSkin {
+            self.next();
+            return true;
+        }
+        false
+    }
+}
@@ -0,0 +1,420 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::components::{BoxColour, Direction};
+use crate::map::LevelMap;
+
+// Number of box configurations explored before the solver gives up
+pub const MAX_NODES: usize = 2_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolverMode {
+    // A box only counts on a spot of its own colour, like in the game
+    Coloured,
+    // Any box counts on any spot, like in classic Sokoban
+    Classic,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Solution {
+    // LURD notation: lowercase for moves, uppercase for pushes
+    pub moves: String,
+    pub move_count: usize,
+    pub push_count: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SolverResult {
+    Solved(Solution),
+    Unsolvable,
+    // The search was stopped before finding out
+    GaveUp,
+}
+
+// Boxes as (cell, colour group) pairs, kept sorted so equal layouts compare equal
+type Boxes = Vec<(u16, u8)>;
+
+// The parts of a level that never change, with cells numbered row by row
+struct Board {
+    width: usize,
+    height: usize,
+    walls: Vec<bool>,
+    // Colour group of the spot on each cell
+    goals: Vec<Option<u8>>,
+    // Group of the colours that fit anything, if the level has one
+    any_group: Option<u8>,
+    // Pushes needed to get a box of each colour group from each cell to the
+    // nearest spot of its group, None when it can never get there
+    distances: Vec<Vec<Option<u16>>>,
+}
+
+impl Board {
+    // Splits a level into the static board, its boxes and the player cell
+    fn new(level_map: &LevelMap, mode: SolverMode) -> Option<(Board, Boxes, u16)> {
+        let width = level_map.width as usize;
+        let height = level_map.height as usize;
+        // Colours that fit anything share one group, kept as None here
+        let mut colours: Vec<Option<BoxColour>> = Vec::new();
+        let mut group = |colour: BoxColour| -> u8 {
+            if mode == SolverMode::Classic {
+                return 0;
+            }
+            let colour = Some(colour).filter(|colour| !colour.is_any());
+            match colours.iter().position(|known| *known == colour) {
+                Some(index) => index as u8,
+                None => {
+                    colours.push(colour);
+                    (colours.len() - 1) as u8
+                }
+            }
+        };
+
+        let mut walls = Vec::with_capacity(width * height);
+        let mut goals = Vec::with_capacity(width * height);
+        let mut boxes = Vec::new();
+        let mut player = None;
+        for (cell, tile) in level_map.tiles.iter().enumerate() {
+            // Anything outside of the level is treated as a wall
+            walls.push(tile.wall || !tile.floor);
+            goals.push(tile.spot_colour.map(&mut group));
+            if let Some(colour) = tile.box_colour {
+                boxes.push((cell as u16, group(colour)));
+            }
+            if tile.player {
+                player = Some(cell as u16);
+            }
+        }
+        boxes.sort_unstable();
+
+        let any_group = colours.iter().position(|colour| colour.is_none()).map(|group| group as u8);
+        let mut board = Board { width, height, walls, goals, any_group, distances: Vec::new() };
+        let groups = boxes
+            .iter()
+            .map(|(_, group)| *group)
+            .chain(board.goals.iter().flatten().copied())
+            .map(|group| group + 1)
+            .max()
+            .unwrap_or(0);
+        board.distances = (0..groups).map(|group| board.pull_distances(group)).collect();
+        Some((board, boxes, player?))
+    }
+
+    // Cell next to the given one, if it is inside the board
+    fn step(&self, cell: u16, direction: Direction) -> Option<u16> {
+        let (dx, dy) = direction.offset();
+        let x = (cell as usize % self.width) as isize + dx as isize;
+        let y = (cell as usize / self.width) as isize + dy as isize;
+        if x < 0 || y < 0 || x >= self.width as isize || y >= self.height as isize {
+            return None;
+        }
+        Some((y as usize * self.width + x as usize) as u16)
+    }
+
+    fn is_free(&self, cell: u16, occupied: &[bool]) -> bool {
+        !self.walls[cell as usize] && !occupied[cell as usize]
+    }
+
+    // Whether a box of the group counts on a spot of the goal group
+    fn fits(&self, group: u8, goal: u8) -> bool {
+        group == goal || self.any_group == Some(group) || self.any_group == Some(goal)
+    }
+
+    // Pulls boxes backwards from every spot of the group: a box can be pushed
+    // from a cell to a spot only if it can be pulled from the spot to the cell
+    fn pull_distances(&self, group: u8) -> Vec<Option<u16>> {
+        let mut distances = vec![None; self.walls.len()];
+        let mut queue = VecDeque::new();
+        for (cell, goal) in self.goals.iter().enumerate() {
+            if goal.is_some_and(|goal| self.fits(group, goal)) && !self.walls[cell] {
+                distances[cell] = Some(0);
+                queue.push_back(cell as u16);
+            }
+        }
+
+        while let Some(cell) = queue.pop_front() {
+            let distance = distances[cell as usize].unwrap_or(0);
+            for direction in Direction::ALL.iter() {
+                let pulled_to = self.step(cell, *direction);
+                let player_to = pulled_to.and_then(|pulled_to| self.step(pulled_to, *direction));
+                if let (Some(pulled_to), Some(player_to)) = (pulled_to, player_to) {
+                    if !self.walls[pulled_to as usize]
+                        && !self.walls[player_to as usize]
+                        && distances[pulled_to as usize].is_none()
+                    {
+                        distances[pulled_to as usize] = Some(distance + 1);
+                        queue.push_back(pulled_to);
+                    }
+                }
+            }
+        }
+
+        distances
+    }
+
+    // Cells a box of the group can never be pushed to a spot from
+    fn is_dead(&self, cell: u16, group: u8) -> bool {
+        self.distances
+            .get(group as usize)
+            .is_none_or(|distances| distances[cell as usize].is_none())
+    }
+
+    fn occupied(&self, boxes: &[(u16, u8)]) -> Vec<bool> {
+        let mut occupied = vec![false; self.walls.len()];
+        for (cell, _) in boxes.iter() {
+            occupied[*cell as usize] = true;
+        }
+        occupied
+    }
+
+    // Every cell the player can walk to without pushing
+    fn reachable(&self, from: u16, occupied: &[bool]) -> Vec<bool> {
+        let mut reachable = vec![false; self.walls.len()];
+        let mut stack = vec![from];
+        reachable[from as usize] = true;
+        while let Some(cell) = stack.pop() {
+            for direction in Direction::ALL.iter() {
+                if let Some(next) = self.step(cell, *direction) {
+                    if !reachable[next as usize] && self.is_free(next, occupied) {
+                        reachable[next as usize] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    // Shortest walk to the target as LURD moves, boxes are obstacles
+    fn walk(&self, from: u16, to: u16, occupied: &[bool]) -> Option<String> {
+        let mut came_from: HashMap<u16, (u16, Direction)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back(from);
+        while let Some(cell) = queue.pop_front() {
+            if cell == to {
+                let mut path = Vec::new();
+                let mut cell = to;
+                while cell != from {
+                    let (previous, direction) = came_from[&cell];
+                    path.push(direction.to_lurd(false));
+                    cell = previous;
+                }
+                return Some(path.iter().rev().collect());
+            }
+            for direction in Direction::ALL.iter() {
+                if let Some(next) = self.step(cell, *direction) {
+                    if next != from && !came_from.contains_key(&next) && self.is_free(next, occupied) {
+                        came_from.insert(next, (cell, *direction));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Lower bound of the pushes left: every box to its nearest spot
+    fn heuristic(&self, boxes: &[(u16, u8)]) -> Option<usize> {
+        boxes.iter().try_fold(0, |total, (cell, group)| {
+            let distance = self.distances[*group as usize][*cell as usize]?;
+            Some(total + distance as usize)
+        })
+    }
+
+    fn is_solved(&self, boxes: &[(u16, u8)]) -> bool {
+        boxes.iter().all(|(cell, group)| self.goals[*cell as usize].is_some_and(|goal| self.fits(*group, goal)))
+    }
+}
+
+// Cells from which a box of the given colour can never be pushed onto one of its spots
+pub fn dead_squares(level_map: &LevelMap, colour: BoxColour) -> Vec<bool> {
+    let mut single_colour = level_map.clone();
+    for tile in single_colour.tiles.iter_mut() {
+        tile.spot_colour = tile.spot_colour.filter(|spot| colour.fits(*spot));
+        tile.box_colour = None;
+    }
+
+    match Board::new(&single_colour, SolverMode::Classic) {
+        Some((board, _, _)) => (0..board.walls.len()).map(|cell| board.is_dead(cell as u16, 0)).collect(),
+        None => vec![false; level_map.tiles.len()],
+    }
+}
+
+struct Node {
+    boxes: Boxes,
+    // Smallest reachable cell, so positions around the same boxes match
+    player: u16,
+    parent: Option<usize>,
+    // Box cell and direction of the push that led here
+    push: Option<(u16, Direction)>,
+    pushes: usize,
+}
+
+// Finds a solution with as few pushes as possible using A* over box layouts
+pub fn solve(level_map: &LevelMap, mode: SolverMode) -> SolverResult {
+    search(level_map, mode, &mut || false)
+}
+
+// Same as solve, but asks should_stop regularly and gives up once it says so
+pub fn search(level_map: &LevelMap, mode: SolverMode, should_stop: &mut dyn FnMut() -> bool) -> SolverResult {
+    let (board, boxes, player) = match Board::new(level_map, mode) {
+        Some(start) => start,
+        None => return SolverResult::Unsolvable,
+    };
+    let estimate = match board.heuristic(&boxes) {
+        Some(estimate) => estimate,
+        None => return SolverResult::Unsolvable,
+    };
+
+    let occupied = board.occupied(&boxes);
+    let normalized = normalize(&board.reachable(player, &occupied));
+    let mut nodes = vec![Node { boxes: boxes.clone(), player: normalized, parent: None, push: None, pushes: 0 }];
+    let mut seen: HashMap<(Boxes, u16), usize> = HashMap::new();
+    seen.insert((boxes, normalized), 0);
+    let mut open = BinaryHeap::new();
+    open.push(Reverse((estimate, 0, 0)));
+    let mut expanded = 0;
+
+    while let Some(Reverse((_, pushes, index))) = open.pop() {
+        // Skip entries superseded by a cheaper path to the same layout
+        if pushes > nodes[index].pushes {
+            continue;
+        }
+        if board.is_solved(&nodes[index].boxes) {
+            return SolverResult::Solved(solution(&board, &nodes, index, player));
+        }
+        expanded += 1;
+        if nodes.len() > MAX_NODES || (expanded % 256 == 0 && should_stop()) {
+            return SolverResult::GaveUp;
+        }
+
+        let occupied = board.occupied(&nodes[index].boxes);
+        let reachable = board.reachable(nodes[index].player, &occupied);
+        for box_index in 0..nodes[index].boxes.len() {
+            let (cell, group) = nodes[index].boxes[box_index];
+            for direction in Direction::ALL.iter() {
+                let from = board.step(cell, direction.opposite());
+                let to = board.step(cell, *direction);
+                let (to, can_push) = match (from, to) {
+                    (Some(from), Some(to)) => (to, reachable[from as usize] && board.is_free(to, &occupied)),
+                    _ => continue,
+                };
+                if !can_push || board.is_dead(to, group) {
+                    continue;
+                }
+
+                let mut boxes = nodes[index].boxes.clone();
+                boxes[box_index].0 = to;
+                boxes.sort_unstable();
+                let child_occupied = board.occupied(&boxes);
+                let player = normalize(&board.reachable(cell, &child_occupied));
+                let pushes = pushes + 1;
+                let estimate = match board.heuristic(&boxes) {
+                    Some(estimate) => estimate,
+                    None => continue,
+                };
+
+                let key = (boxes, player);
+                let child = match seen.get(&key) {
+                    Some(&known) if nodes[known].pushes <= pushes => continue,
+                    Some(&known) => known,
+                    None => {
+                        nodes.push(Node { boxes: key.0.clone(), player, parent: None, push: None, pushes });
+                        seen.insert(key, nodes.len() - 1);
+                        nodes.len() - 1
+                    }
+                };
+                nodes[child].parent = Some(index);
+                nodes[child].push = Some((cell, *direction));
+                nodes[child].pushes = pushes;
+                open.push(Reverse((pushes + estimate, pushes, child)));
+            }
+        }
+    }
+
+    SolverResult::Unsolvable
+}
+
+fn normalize(reachable: &[bool]) -> u16 {
+    reachable.iter().position(|reachable| *reachable).unwrap_or(0) as u16
+}
+
+// Replays the pushes leading to the node, walking the player between them
+fn solution(board: &Board, nodes: &[Node], index: usize, player: u16) -> Solution {
+    let mut pushes = Vec::new();
+    let mut current = Some(index);
+    while let Some(index) = current {
+        if let Some(push) = nodes[index].push {
+            pushes.push(push);
+        }
+        current = nodes[index].parent;
+    }
+    pushes.reverse();
+
+    let mut boxes: Vec<u16> = nodes[0].boxes.iter().map(|(cell, _)| *cell).collect();
+    let mut player = player;
+    let mut moves = String::new();
+    for (cell, direction) in pushes.iter() {
+        let mut occupied = vec![false; board.walls.len()];
+        for cell in boxes.iter() {
+            occupied[*cell as usize] = true;
+        }
+        let from = board.step(*cell, direction.opposite()).unwrap_or(*cell);
+        moves.push_str(&board.walk(player, from, &occupied).unwrap_or_default());
+        moves.push(direction.to_lurd(true));
+
+        let to = board.step(*cell, *direction).unwrap_or(*cell);
+        for box_cell in boxes.iter_mut().filter(|box_cell| **box_cell == *cell) {
+            *box_cell = to;
+        }
+        player = *cell;
+    }
+
+    Solution {
+        move_count: moves.len(),
+        push_count: pushes.len(),
+        moves,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::Location;
+    use crate::map::{self, test_level};
+
+    #[test]
+    fn solves_single_push() {
+        let result = solve(&test_level("#####\n#@$.#\n#####"), SolverMode::Coloured);
+        assert_eq!(result, SolverResult::Solved(Solution { moves: "R".to_string(), move_count: 1, push_count: 1 }));
+    }
+
+    #[test]
+    fn solves_with_fewest_pushes() {
+        let level_map = test_level("######\n#    #\n# $  #\n#@$ .#\n#   .#\n######");
+        let solution = match solve(&level_map, SolverMode::Classic) {
+            SolverResult::Solved(solution) => solution,
+            result => panic!("expected a solution, got {:?}", result),
+        };
+        // Either way round each box is at least three pushes from its spot
+        assert_eq!(solution.push_count, 6);
+        assert_eq!(solution.push_count, solution.moves.chars().filter(|c| c.is_uppercase()).count());
+        assert_eq!(solution.move_count, solution.moves.len());
+    }
+
+    #[test]
+    fn box_in_corner_is_unsolvable() {
+        let result = solve(&test_level("#####\n#@ .#\n#$  #\n#####"), SolverMode::Coloured);
+        assert_eq!(result, SolverResult::Unsolvable);
+    }
+
+    #[test]
+    fn colours_must_match_spots() {
+        let level_map = map::parse_map(
+            "W W W W W W\nW P RB BS W\nW . BB RS W\nW W W W W W",
+            Location::new("test", 1, 1),
+        )
+        .unwrap();
+        assert!(matches!(solve(&level_map, SolverMode::Classic), SolverResult::Solved(_)));
+        assert_eq!(solve(&level_map, SolverMode::Coloured), SolverResult::Unsolvable);
+    }
+}
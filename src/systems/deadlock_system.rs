@@ -0,0 +1,61 @@
+use specs::{Entities, Join, Read, ReadStorage, System, Write, WriteStorage};
+
+use crate::components::*;
+use crate::deadlock::{self, DeadSquares};
+use crate::events::Event;
+use crate::map;
+use crate::resources::{CurrentMap, EventQueue};
+
+pub struct DeadlockSystem {}
+
+// System implementation
+impl<'a> System<'a> for DeadlockSystem {
+    // Data
+    type SystemData = (
+        Write<'a, EventQueue>,
+        Read<'a, CurrentMap>,
+        Read<'a, DeadSquares>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Box>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, Deadlocked>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut events,
+            current_map,
+            dead_squares,
+            entities,
+            positions,
+            boxes,
+            players,
+            mut deadlocked) = data;
+
+        // Only look again when a box has moved (pushed, undone or redone)
+        let box_moved = events.events.iter().any(|event| match event {
+            Event::EntityMoved(moved) => boxes.get(entities.entity(moved.id)).is_some(),
+            _ => false,
+        });
+        if !box_moved {
+            return;
+        }
+
+        let level_map = map::with_current_layout(&current_map.map, &positions, &boxes, &players);
+        let stuck = deadlock::find_deadlocked_boxes(&level_map, &dead_squares);
+        let was_deadlocked = (&deadlocked).join().next().is_some();
+        deadlocked.clear();
+
+        for (entity, position, _box) in (&entities, &positions, &boxes).join() {
+            if stuck.contains(&(position.x, position.y)) {
+                deadlocked
+                    .insert(entity, Deadlocked)
+                    .expect("expected deadlocked marker inserted");
+            }
+        }
+
+        if !was_deadlocked && !stuck.is_empty() {
+            events.events.push(Event::Deadlocked {});
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use crate::{
-    audio::AudioStore,
     components::*,
     events::{BoxPlacedOnSpot, EntityMoved, Event},
     resources::EventQueue,
@@ -14,7 +13,6 @@ impl<'a> System<'a> for EventSystem {
     // Data
     type SystemData = (
         Write<'a, EventQueue>,
-        Write<'a, AudioStore>,
         Entities<'a>,
         ReadStorage<'a, Box>,
         ReadStorage<'a, BoxSpot>,
@@ -23,7 +21,6 @@ impl<'a> System<'a> for EventSystem {
 
     fn run(&mut self, data: Self::SystemData) {
         let (mut event_queue,
-            mut audio_store,
             entities,
             boxes,
             box_spots,
@@ -31,51 +28,31 @@ impl<'a> System<'a> for EventSystem {
 
         let mut new_events = Vec::new();
 
+        // Moved boxes may have landed on a spot, the front end plays the
+        // sounds of every event
         for event in event_queue.events.drain(..) {
-            println!("New event: {:?}", event);
-
-            match event {
-                Event::PlayerHitObstacle => {
-                    // play sound here
-                    audio_store.play_sound(&"wall".to_string());
-                }
-                Event::EntityMoved(EntityMoved { id }) => {
-                    // An entity was just moved, check if it was a box and fire
-                    // more events if it's been moved on a spot.
-                    if let Some(the_box) = boxes.get(entities.entity(id)) {
-                        let box_spots_with_positions: HashMap<(u8, u8), &BoxSpot> =
-                            (&box_spots, &positions)
-                                .join()
-                                .map(|t| ((t.1.x, t.1.y), t.0))
-                                .collect::<HashMap<_, _>>();
-
-                        if let Some(box_position) = positions.get(entities.entity(id)) {
-                            // Check if there is a spot on this position, and if there
-                            // is if it's the correct or incorrect type
-                            if let Some(box_spot) =
-                            box_spots_with_positions.get(&(box_position.x, box_position.y))
-                            {
-                                new_events.push(Event::BoxPlacedOnSpot(BoxPlacedOnSpot {
-                                    is_correct_spot: (box_spot.colour == the_box.colour),
-                                }));
-                            }
+            if let Event::EntityMoved(EntityMoved { id }) = event {
+                // An entity was just moved, check if it was a box and fire
+                // more events if it's been moved on a spot.
+                if let Some(the_box) = boxes.get(entities.entity(id)) {
+                    let box_spots_with_positions: HashMap<(u8, u8), &BoxSpot> =
+                        (&box_spots, &positions)
+                            .join()
+                            .map(|t| ((t.1.x, t.1.y), t.0))
+                            .collect::<HashMap<_, _>>();
+
+                    if let Some(box_position) = positions.get(entities.entity(id)) {
+                        // Check if there is a spot on this position, and if there
+                        // is if it's the correct or incorrect type
+                        if let Some(box_spot) =
+                        box_spots_with_positions.get(&(box_position.x, box_position.y))
+                        {
+                            new_events.push(Event::BoxPlacedOnSpot(BoxPlacedOnSpot {
+                                is_correct_spot: the_box.colour.fits(box_spot.colour),
+                            }));
                         }
                     }
                 }
-                Event::BoxPlacedOnSpot(BoxPlacedOnSpot { is_correct_spot }) => {
-                    // play sound here
-                    let sound = if is_correct_spot {
-                        "correct"
-                    } else {
-                        "incorrect"
-                    };
-
-                    audio_store.play_sound(&sound.to_string())
-                }
-                Event::PlayerWon => {
-                    let sound = "won";
-                    audio_store.play_sound(&sound.to_string())
-                }
             }
         }
 
@@ -1,9 +1,11 @@
 use std::collections::HashMap;
 
-use specs::{Join, ReadStorage, System, Write};
+use specs::{Join, Read, ReadStorage, System, Write};
 
-use crate::components::{Box, BoxSpot, Position};
-use crate::resources::{Gameplay, GameplayState, EventQueue};
+use crate::collection::Collection;
+use crate::components::{Box, BoxSpot, Deadlocked, Position};
+use crate::profile::{LevelScore, Profile};
+use crate::resources::{Gameplay, GameplayState, EventQueue, MoveHistory, Time};
 use crate::events::Event;
 
 pub struct GameplayStateSystem {}
@@ -13,17 +15,27 @@ impl<'a> System<'a> for GameplayStateSystem {
     type SystemData = (
         Write<'a, EventQueue>,
         Write<'a, Gameplay>,
+        Read<'a, Time>,
+        Read<'a, MoveHistory>,
+        Read<'a, Collection>,
+        Write<'a, Profile>,
         ReadStorage<'a, Position>,
         ReadStorage<'a, Box>,
         ReadStorage<'a, BoxSpot>,
+        ReadStorage<'a, Deadlocked>,
     );
 
     fn run(&mut self, data: Self::SystemData) {
         let (mut events,
             mut gameplay_state,
+            time,
+            history,
+            collection,
+            mut profile,
             positions,
             boxes,
-            box_spots) = data;
+            box_spots,
+            deadlocked) = data;
 
         // get all boxes indexed by position
         let boxes_by_position: HashMap<(u8, u8), &Box> = (&positions, &boxes)
@@ -32,12 +44,18 @@ impl<'a> System<'a> for GameplayStateSystem {
             .collect::<HashMap<_, _>>();
 
         // loop through all box spots and check if there is a corresponding
-        // box at that position
+        // box at that position. Moves can be undone, so a won level goes back to
+        // playing as soon as any spot is left or gets a box of the wrong colour.
         for (box_spot, position) in (&box_spots, &positions).join() {
             match boxes_by_position.get(&(position.x, position.y)) {
-                Some(cur_box) => if cur_box.colour == box_spot.colour { continue; } else { return; },
-                None => {
-                    gameplay_state.state = GameplayState::Playing;
+                Some(cur_box) if cur_box.colour.fits(box_spot.colour) => continue,
+                _ => {
+                    gameplay_state.state = if (&deadlocked).join().next().is_some() {
+                        GameplayState::Deadlocked
+                    } else {
+                        GameplayState::Playing
+                    };
+                    gameplay_state.won_at = None;
                     return;
                 }
             }
@@ -47,8 +65,25 @@ impl<'a> System<'a> for GameplayStateSystem {
         // game has been won
         if gameplay_state.state != GameplayState::Won {
             gameplay_state.state = GameplayState::Won;
+            gameplay_state.won_at = Some(time.delta);
             events.events.push(Event::PlayerWon {});
-            println!("You won in {} moves", gameplay_state.moves_count)
+
+            // Levels tried from the editor have no collection name and are not
+            // scored, nor are wins replayed from a save
+            let level = collection
+                .levels
+                .get(gameplay_state.level)
+                .filter(|_| !collection.name.is_empty() && !gameplay_state.restoring);
+            if let Some(level) = level {
+                let pushes = history.undo_stack.iter().filter(|record| record.is_push()).count() as u32;
+                let score = LevelScore::new(
+                    gameplay_state.moves_count,
+                    pushes,
+                    gameplay_state.elapsed(time.delta),
+                    history.lurd(),
+                );
+                profile.record(&collection.name, &level.title, score);
+            }
         }
     }
 }
\ No newline at end of file
@@ -1,16 +1,55 @@
 use std::collections::HashMap;
+use std::time::Duration;
 
-use ggez::event::KeyCode;
-use specs::{Entities, Join, ReadStorage, System, Write, WriteStorage};
+use specs::{Entities, Entity, Join, Read, ReadStorage, System, Write, WriteStorage};
 use specs::world::Index;
 
+use crate::actions::Action;
+use crate::animation::Animation;
+use crate::camera::Camera;
 use crate::components::*;
-use crate::constants::*;
+use crate::constants::INPUT_BUFFER;
+use crate::editor::Editor;
 use crate::events::{EntityMoved, Event};
-use crate::resources::{EventQueue, Gameplay, InputQueue};
+use crate::resources::{EntityMove, EventQueue, Gameplay, Hint, InputQueue, LevelChange, LevelQueue, MapSize, MoveHistory, MoveRecord, PushRule, Replay, Route, Ruleset, Time};
+use crate::screens::{Screen, Screens};
+use crate::skins::Skins;
 
 pub struct InputSystem {}
 
+impl InputSystem {
+    // Animates the entity from the tile it was on to its new position
+    fn start_motion(motions: &mut WriteStorage<Motion>, entity: Entity, from: Position, now: Duration) {
+        let steps = motions.get(entity).map_or(0, |motion| motion.steps) + 1;
+        motions
+            .insert(entity, Motion { from: (from.x, from.y), started_at: now, steps })
+            .expect("expected a live entity");
+    }
+
+    // Puts every entity of the record back to where it was before (undo) or
+    // after (redo) the move and fires the usual moved events for them
+    fn replay_record(
+        record: &MoveRecord,
+        undo: bool,
+        entities: &Entities,
+        positions: &mut WriteStorage<Position>,
+        motions: &mut WriteStorage<Motion>,
+        now: Duration,
+        events: &mut EventQueue,
+    ) {
+        for entity_move in record.moves.iter() {
+            let entity = entities.entity(entity_move.id);
+            if let Some(position) = positions.get_mut(entity) {
+                let from = *position;
+                *position = if undo { entity_move.from } else { entity_move.to };
+                Self::start_motion(motions, entity, from, now);
+            }
+
+            events.events.push(Event::EntityMoved(EntityMoved { id: entity_move.id }));
+        }
+    }
+}
+
 // System implementation
 impl<'a> System<'a> for InputSystem {
     // Data
@@ -18,8 +57,22 @@ impl<'a> System<'a> for InputSystem {
         Write<'a, EventQueue>,
         Write<'a, InputQueue>,
         Write<'a, Gameplay>,
+        Write<'a, MoveHistory>,
+        Write<'a, LevelQueue>,
+        Read<'a, MapSize>,
+        Read<'a, Ruleset>,
+        Write<'a, Hint>,
+        Write<'a, Replay>,
+        Read<'a, Time>,
+        Write<'a, Screens>,
+        Read<'a, Editor>,
+        Write<'a, Route>,
+        Read<'a, Animation>,
+        Write<'a, Camera>,
+        Write<'a, Skins>,
         Entities<'a>,
         WriteStorage<'a, Position>,
+        WriteStorage<'a, Motion>,
         ReadStorage<'a, Player>,
         ReadStorage<'a, Movable>,
         ReadStorage<'a, Immovable>,
@@ -30,95 +83,362 @@ impl<'a> System<'a> for InputSystem {
             mut events,
             mut input_queue,
             mut gameplay,
+            mut history,
+            mut level_queue,
+            map_size,
+            ruleset,
+            mut hint,
+            mut replay,
+            time,
+            mut screens,
+            editor,
+            mut route,
+            animation,
+            mut camera,
+            mut skins,
             entities,
             mut positions,
+            mut motions,
             players,
             movables,
             immovables,
         ) = data;
 
+        // Menus handle their own input, and the game stands still under them
+        if screens.current() != Screen::Playing {
+            return;
+        }
+
+        // Nothing moves before the last move is drawn. The first INPUT_BUFFER
+        // actions pressed in the meantime wait their turn, the later ones are
+        // dropped, and the camera and the skin change right away. Replays keep
+        // their own pace.
+        let animating = (&motions).join().any(|motion| animation.is_playing(motion, time.delta));
+        if animating && !replay.is_active() {
+            let mut queued = 0;
+            input_queue.actions.retain(|action| {
+                if camera.apply(*action) || skins.apply(*action) {
+                    return false;
+                }
+                queued += 1;
+                queued <= INPUT_BUFFER
+            });
+            return;
+        }
+
+        // Get the first action requested
+        let action = input_queue.pop_front();
+
+        // Looking around or changing the look does not change the board
+        if action.is_some_and(|action| camera.apply(action) || skins.apply(action)) {
+            return;
+        }
+
+        // While a replay runs only the playback controls are listened to
+        let direction = if replay.is_active() {
+            match action {
+                Some(Action::ReplayPause) => replay.paused = !replay.paused,
+                Some(Action::ReplayStep) => {
+                    replay.paused = true;
+                    replay.step_requested = true;
+                }
+                Some(Action::ReplayFaster) => replay.change_speed(2.0),
+                Some(Action::ReplaySlower) => replay.change_speed(0.5),
+                Some(Action::Back) => replay.stop(),
+                _ => (),
+            }
+            match replay.next_step(time.delta) {
+                Some(direction) => direction,
+                None => return,
+            }
+        } else {
+            // Anything but another click stops walking a clicked path
+            match action {
+                Some(Action::Click { .. }) | None => (),
+                Some(_) => route.clear(),
+            }
+
+            match action {
+                None => match route.next_step(time.delta) {
+                    Some(direction) => direction,
+                    None => return,
+                },
+                Some(Action::Click { x, y }) => {
+                    route.clicked = Some((x, y));
+                    route.steps.clear();
+                    return;
+                }
+                Some(Action::Move(direction)) => direction,
+                Some(Action::Undo) => {
+                    if let Some(record) = history.undo_stack.pop() {
+                        Self::replay_record(&record, true, &entities, &mut positions, &mut motions, time.delta, &mut events);
+                        gameplay.moves_count = gameplay.moves_count.saturating_sub(1);
+                        history.redo_stack.push(record);
+                    }
+                    return;
+                }
+                Some(Action::Redo) => {
+                    if let Some(record) = history.redo_stack.pop() {
+                        Self::replay_record(&record, false, &entities, &mut positions, &mut motions, time.delta, &mut events);
+                        gameplay.moves_count += 1;
+                        history.undo_stack.push(record);
+                    }
+                    return;
+                }
+                Some(Action::Hint) => {
+                    hint.requested = true;
+                    return;
+                }
+                Some(Action::Restart) => {
+                    level_queue.change = Some(LevelChange::Restart);
+                    return;
+                }
+                Some(Action::NextLevel) => {
+                    level_queue.change = Some(LevelChange::Next);
+                    return;
+                }
+                Some(Action::PreviousLevel) => {
+                    level_queue.change = Some(LevelChange::Previous);
+                    return;
+                }
+                // A level tried from the editor goes straight back to it
+                Some(Action::Back) => {
+                    if editor.stashed.is_some() {
+                        screens.pop();
+                    } else {
+                        screens.push(Screen::Paused { selected: 0 });
+                    }
+                    return;
+                }
+                Some(Action::ReplayMoves) => {
+                    if !history.undo_stack.is_empty() {
+                        *replay = Replay::new(&history.lurd());
+                        replay.last_step = time.delta;
+                        level_queue.change = Some(LevelChange::Restart);
+                    }
+                    return;
+                }
+                _ => return,
+            }
+        };
+
         let mut to_move = Vec::new();
 
         for (position, _player) in (&positions, &players).join() {
-            // Get the first key pressed
-            if let Some(key) = input_queue.keys_pressed.pop() {
-                // get all the movables and immovables
-                let mov: HashMap<(u8, u8), Index> = (&entities, &movables, &positions)
-                    .join()
-                    .map(|t| ((t.2.x, t.2.y), t.0.id()))
-                    .collect::<HashMap<_, _>>();
-                let immov: HashMap<(u8, u8), Index> = (&entities, &immovables, &positions)
-                    .join()
-                    .map(|t| ((t.2.x, t.2.y), t.0.id()))
-                    .collect::<HashMap<_, _>>();
-
-                // Now iterate through current position to the end of the map
-                // on the correct axis and check what needs to move.
-                let (start, end, is_x) = match key {
-                    KeyCode::Up => (position.y, 0, false),
-                    KeyCode::Down => (position.y, MAP_HEIGHT, false),
-                    KeyCode::Left => (position.x, 0, true),
-                    KeyCode::Right => (position.x, MAP_WIDTH, true),
-                    _ => continue,
-                };
+            // get all the movables and immovables
+            let mov: HashMap<(u8, u8), Index> = (&entities, &movables, &positions)
+                .join()
+                .map(|t| ((t.2.x, t.2.y), t.0.id()))
+                .collect::<HashMap<_, _>>();
+            let immov: HashMap<(u8, u8), Index> = (&entities, &immovables, &positions)
+                .join()
+                .map(|t| ((t.2.x, t.2.y), t.0.id()))
+                .collect::<HashMap<_, _>>();
 
-                let range = if start < end {
-                    (start..=end).collect::<Vec<_>>()
+            // Now iterate through current position to the end of the map
+            // on the correct axis and check what needs to move.
+            let (start, end, is_x) = match direction {
+                Direction::Up => (position.y, 0, false),
+                Direction::Down => (position.y, map_size.height, false),
+                Direction::Left => (position.x, 0, true),
+                Direction::Right => (position.x, map_size.width, true),
+            };
+
+            let range = if start < end {
+                (start..=end).collect::<Vec<_>>()
+            } else {
+                (end..=start).rev().collect::<Vec<_>>()
+            };
+
+            for x_or_y in range {
+                let pos = if is_x {
+                    (x_or_y, position.y)
                 } else {
-                    (end..=start).rev().collect::<Vec<_>>()
+                    (position.x, x_or_y)
                 };
 
-                for x_or_y in range {
-                    let pos = if is_x {
-                        (x_or_y, position.y)
-                    } else {
-                        (position.x, x_or_y)
-                    };
-
-                    // find a movable
-                    // if it exists, we try to move it and continue
-                    // if it doesn't exist, we continue and try to find an immovable instead
-                    match mov.get(&pos) {
-                        Some(id) => to_move.push((key, id.clone())),
-                        None => {
-                            // find an immovable
-                            // if it exists, we need to stop and not move anything
-                            // if it doesn't exist, we stop because we found a gap
-                            match immov.get(&pos) {
-                                Some(_id) => {
-                                    to_move.clear();
-                                    events.events.push(Event::PlayerHitObstacle {});
-                                }
-                                None => (),
-                            }
-                            break;
+                // find a movable
+                // if it exists, we try to move it and continue
+                // if it doesn't exist, we continue and try to find an immovable instead
+                match mov.get(&pos) {
+                    // with the single push rule the player (first movable) can
+                    // only move one box, a box behind it blocks like a wall
+                    Some(_id) if ruleset.push_rule == PushRule::Single && to_move.len() == 2 => {
+                        to_move.clear();
+                        events.events.push(Event::PlayerHitObstacle {});
+                        break;
+                    }
+                    Some(id) => to_move.push(*id),
+                    None => {
+                        // find an immovable
+                        // if it exists, we need to stop and not move anything
+                        // if it doesn't exist, we stop because we found a gap
+                        if immov.contains_key(&pos) {
+                            to_move.clear();
+                            events.events.push(Event::PlayerHitObstacle {});
                         }
+                        break;
                     }
                 }
             }
         }
 
         // We've just moved, so let's increase the number of moves
-        if to_move.len() > 0 {
+        if !to_move.is_empty() {
             gameplay.moves_count += 1;
-            println!("To move: {}", to_move.len());
+        } else {
+            // Something got in the way of the path being walked
+            route.clear();
         }
 
         // Now actually move what needs to be moved
-        for (key, id) in to_move {
-            let position = positions.get_mut(entities.entity(id));
-            if let Some(position) = position {
-                match key {
-                    KeyCode::Up => position.y -= 1,
-                    KeyCode::Down => position.y += 1,
-                    KeyCode::Left => position.x -= 1,
-                    KeyCode::Right => position.x += 1,
-                    _ => (),
-                }
+        let mut record = MoveRecord { direction, moves: Vec::new() };
+        let (dx, dy) = direction.offset();
+        for id in to_move {
+            let entity = entities.entity(id);
+            if let Some(position) = positions.get_mut(entity) {
+                let from = *position;
+                position.x = (position.x as i16 + dx as i16) as u8;
+                position.y = (position.y as i16 + dy as i16) as u8;
+                record.moves.push(EntityMove { id, from, to: *position });
+                Self::start_motion(&mut motions, entity, from, time.delta);
             }
 
             // Fire an event for the entity that just moved
             events.events.push(Event::EntityMoved(EntityMoved { id }));
         }
+
+        // Remember the move so it can be undone later
+        if !record.moves.is_empty() {
+            history.record(record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use specs::WorldExt;
+
+    use crate::actions::Action;
+    use crate::animation::Animation;
+    use crate::camera::Camera;
+    use crate::components::Direction;
+    use crate::constants::INPUT_BUFFER;
+    use crate::map::write_xsb;
+    use crate::events::Event;
+    use crate::resources::{InputQueue, MoveHistory, PushRule, Ruleset};
+    use crate::simulation::test_simulation;
+    use crate::skins::Skins;
+
+    #[test]
+    fn undo_and_redo_a_push() {
+        let mut simulation = test_simulation("######\n#@$ .#\n######");
+        simulation.step(Action::Move(Direction::Right));
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "######\n# @$.#\n######\n");
+        assert_eq!(simulation.moves_count(), 1);
+
+        simulation.step(Action::Undo);
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "######\n#@$ .#\n######\n");
+        assert_eq!(simulation.moves_count(), 0);
+
+        simulation.step(Action::Redo);
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "######\n# @$.#\n######\n");
+        assert_eq!(simulation.moves_count(), 1);
+    }
+
+    #[test]
+    fn undo_with_nothing_to_undo() {
+        let mut simulation = test_simulation("#####\n#@$.#\n#####");
+        assert!(simulation.step(Action::Undo).is_empty());
+        assert_eq!(simulation.moves_count(), 0);
+    }
+
+    #[test]
+    fn new_move_clears_redo() {
+        let mut simulation = test_simulation("######\n#@  .#\n# $  #\n#    #\n######");
+        simulation.step(Action::Move(Direction::Right));
+        simulation.step(Action::Undo);
+        simulation.step(Action::Move(Direction::Down));
+        assert!(simulation.world.read_resource::<MoveHistory>().redo_stack.is_empty());
+
+        simulation.step(Action::Redo);
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "######\n#   .#\n#@$  #\n#    #\n######\n");
+        assert_eq!(simulation.moves_count(), 1);
+    }
+
+    #[test]
+    fn single_rule_stops_at_a_second_box() {
+        let mut simulation = test_simulation("########\n#@$$ ..#\n########");
+        let events = simulation.step(Action::Move(Direction::Right));
+        assert!(events.iter().any(|event| matches!(event, Event::PlayerHitObstacle)));
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "########\n#@$$ ..#\n########\n");
+        assert_eq!(simulation.moves_count(), 0);
+    }
+
+    #[test]
+    fn chain_rule_pushes_every_box_in_the_row() {
+        let mut simulation = test_simulation("########\n#@$$ ..#\n########");
+        simulation.world.write_resource::<Ruleset>().push_rule = PushRule::Chain;
+        simulation.step(Action::Move(Direction::Right));
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "########\n# @$$..#\n########\n");
+        assert_eq!(simulation.moves_count(), 1);
+    }
+
+    #[test]
+    fn chain_rule_stops_at_a_wall() {
+        let mut simulation = test_simulation("#######\n# @$$##\n#   ..#\n#######");
+        simulation.world.write_resource::<Ruleset>().push_rule = PushRule::Chain;
+        let events = simulation.step(Action::Move(Direction::Right));
+        assert!(events.iter().any(|event| matches!(event, Event::PlayerHitObstacle)));
+        assert_eq!(write_xsb(&simulation.level_map()).unwrap(), "#######\n# @$$##\n#   ..#\n#######\n");
+    }
+
+    // Starts a move to the right that is drawn for a while, the clock standing still
+    fn animating_simulation() -> crate::simulation::Simulation {
+        let mut simulation = test_simulation("########\n#@   $.#\n########");
+        simulation.world.write_resource::<Animation>().duration = Duration::from_millis(100);
+        simulation.step(Action::Move(Direction::Right));
+        simulation
+    }
+
+    #[test]
+    fn first_moves_wait_while_animating() {
+        let mut simulation = animating_simulation();
+        let pressed = [
+            Action::Move(Direction::Right),
+            Action::Undo,
+            Action::Move(Direction::Left),
+            Action::Move(Direction::Down),
+            Action::Move(Direction::Up),
+        ];
+        simulation.world.write_resource::<InputQueue>().actions.extend(pressed);
+        simulation.update();
+
+        let expected = vec![Action::Move(Direction::Right), Action::Undo];
+        assert_eq!(simulation.world.read_resource::<InputQueue>().actions, expected);
+        assert_eq!(simulation.moves_count(), 1);
+    }
+
+    #[test]
+    fn held_undo_waits_like_a_move() {
+        let mut simulation = animating_simulation();
+        simulation.world.write_resource::<InputQueue>().actions.extend([Action::Undo; 5]);
+        simulation.update();
+
+        assert_eq!(simulation.world.read_resource::<InputQueue>().actions, vec![Action::Undo; INPUT_BUFFER]);
+    }
+
+    #[test]
+    fn camera_and_skin_change_while_animating() {
+        let mut simulation = animating_simulation();
+        let mode = simulation.world.read_resource::<Camera>().mode;
+        simulation.step(Action::NextCameraMode);
+        simulation.step(Action::NextSkin);
+
+        assert_eq!(simulation.world.read_resource::<Camera>().mode, mode.next());
+        assert!(simulation.world.read_resource::<Skins>().changed);
+        assert!(simulation.world.read_resource::<InputQueue>().actions.is_empty());
     }
-}
\ No newline at end of file
+}
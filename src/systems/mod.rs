@@ -1,10 +1,16 @@
 pub use self::input_system::InputSystem;
+#[cfg(feature = "frontend")]
 pub use self::rendering_system::RenderingSystem;
 pub use self::gameplay_state_system::GameplayStateSystem;
 pub use self::event_system::EventSystem;
+pub use self::deadlock_system::DeadlockSystem;
+pub use self::screen_system::ScreenSystem;
 
 mod input_system;
+#[cfg(feature = "frontend")]
 mod rendering_system;
 mod gameplay_state_system;
 mod event_system;
+mod deadlock_system;
+mod screen_system;
 
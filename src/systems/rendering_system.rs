@@ -1,26 +1,67 @@
 use ggez::{Context, graphics, timer};
-use ggez::graphics::{DrawParam, Image, Color};
+use ggez::graphics::{DrawParam, Color, Rect};
 use ggez::nalgebra as na;
-use specs::{Join, ReadStorage, System, Read};
+use specs::{Entities, Join, ReadStorage, System, Read, Write};
 
-use crate::components::{Position, Renderable, RenderableKind};
-use crate::constants::{TILE_SIZE, MAP_WIDTH, STATE_DLMR_WIDTH, STATE_DLMR_HEIGHT};
-use crate::resources::{Gameplay, Time};
-use std::time::Duration;
-use itertools::Itertools;
+use crate::actions::Action;
+use crate::animation::Animation;
+use crate::bindings::{Bindings, BINDABLE_ACTIONS};
+use crate::camera::Camera;
+use crate::collection::Collection;
+use crate::components::{Deadlocked, Direction, Motion, Position, Renderable};
+use crate::constants::TILE_SIZE;
+use crate::editor::Editor;
+use crate::layout::{self, Layout};
+use crate::profile::Profile;
+use crate::resources::{Gameplay, Hint, HintStatus, LevelLoadError, MoveHistory, Replay, Route, Time};
+use crate::screens::{MenuItem, Screen, Screens};
+use crate::skins::Skins;
+use crate::textures::TextureCache;
 use std::collections::HashMap;
-use ggez::graphics::spritebatch::SpriteBatch;
 
 pub struct RenderingSystem<'a> {
     pub context: &'a mut Context,
 }
 
+const LINE_HEIGHT: f32 = 20.0;
+
+// Tiles to draw by z and image, with the sheet cell they show if any
+type RenderingBatches = HashMap<u8, HashMap<String, Vec<(DrawParam, Option<layout::Rect>)>>>;
+
+fn text_color() -> Color {
+    Color::new(0.0, 0.0, 0.0, 1.0)
+}
+
+fn selected_color() -> Color {
+    Color::new(0.1, 0.3, 0.9, 1.0)
+}
+
+fn faded_color() -> Color {
+    Color::new(0.4, 0.4, 0.4, 1.0)
+}
+
+// Key of an action for the hints on screen, the one the menus take when the
+// action has none
+fn key_or(bindings: &Bindings, action: Action, fallback: &str) -> String {
+    bindings.key_name(action).unwrap_or_else(|| fallback.to_string())
+}
+
+// Menu entries, the highlighted one marked
+fn menu_lines(menu: &[MenuItem], selected: usize) -> Vec<(String, Color)> {
+    menu.iter()
+        .enumerate()
+        .map(|(index, item)| if index == selected {
+            (format!("> {}", item), selected_color())
+        } else {
+            (format!("  {}", item), text_color())
+        })
+        .collect()
+}
+
 impl RenderingSystem<'_> {
-    pub fn draw_text(&mut self, text_string: &str, line: u8) {
-        let text_from_x_tile = MAP_WIDTH + STATE_DLMR_WIDTH;
-        let text_from_y_tile = STATE_DLMR_HEIGHT + line;
-        let x = text_from_x_tile as f32 * TILE_SIZE;
-        let y = text_from_y_tile as f32 * TILE_SIZE;
+    // HUD text, placed by the layout
+    pub fn draw_text(&mut self, text_string: &str, line: u8, layout: &Layout) {
+        let (x, y) = layout.hud_line(line);
 
         let text = graphics::Text::new(text_string);
         let destination = na::Point2::new(x, y);
@@ -37,47 +78,250 @@ impl RenderingSystem<'_> {
             .expect("expected drawing queued text");
     }
 
-    pub fn get_image(&mut self, renderable: &Renderable, delta: Duration) -> String {
-        let path_index = match renderable.kind() {
-            RenderableKind::Static => {
-                0
-            }
-            RenderableKind::Animated => {
-                // If we have multiple, we want to select the right one based on the delta time.
-                // First we get the delta in milliseconds, we % by 1000 to get the milliseconds
-                // only and finally we divide by 250 to get a number between 0 and 4. If it's 4
-                // we technically are on the next iteration of the loop (or on 0), but we will let
-                // the renderable handle this logic of wrapping frames.
-                ((delta.as_millis() % 1000) / 250) as usize
-            }
+    // The keys that lead away from the broken level are the bound ones
+    pub fn draw_error(&mut self, error_string: &str, bindings: &Bindings) {
+        let keys: Vec<String> = [Action::Restart, Action::NextLevel, Action::PreviousLevel]
+            .iter()
+            .filter_map(|action| bindings.key_name(*action))
+            .collect();
+        let press = match keys.split_last() {
+            None => String::new(),
+            Some((last, [])) => format!("\n\nPress {}", last),
+            Some((last, rest)) => format!("\n\nPress {} or {}", rest.join(", "), last),
         };
-        renderable.path(path_index)
+        let width = graphics::screen_coordinates(self.context).w;
+        let mut text = graphics::Text::new(format!("Cannot start level:\n{}{}", error_string, press));
+        text.set_bounds(na::Point2::new(width - TILE_SIZE, f32::INFINITY), graphics::Align::Left);
+        let destination = na::Point2::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+        let color = Color::new(0.8, 0.0, 0.0, 1.0);
+
+        graphics::draw(self.context, &text, graphics::DrawParam::new().dest(destination).color(color))
+            .expect("expected drawing error text");
+    }
+
+    pub fn draw_highlight(&mut self, x: u8, y: u8, color: Color) {
+        let rect = graphics::Rect::new(x as f32 * TILE_SIZE, y as f32 * TILE_SIZE, TILE_SIZE, TILE_SIZE);
+        let mesh = graphics::Mesh::new_rectangle(self.context, graphics::DrawMode::fill(), rect, color)
+            .expect("expected highlight mesh");
+
+        graphics::draw(self.context, &mesh, graphics::DrawParam::new()).expect("expected drawing highlight");
+    }
+
+    // Lines of text from the top left corner, for the menus
+    pub fn draw_lines(&mut self, lines: &[(String, Color)]) {
+        for (index, (line, color)) in lines.iter().enumerate() {
+            let text = graphics::Text::new(line.as_str());
+            let destination = na::Point2::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0 + index as f32 * LINE_HEIGHT);
+            graphics::draw(self.context, &text, graphics::DrawParam::new().dest(destination).color(*color))
+                .expect("expected drawing menu text");
+        }
+    }
+
+    // Board coordinates from here on, until end_board
+    pub fn begin_board(&mut self, camera: &Camera) {
+        let transform = DrawParam::new()
+            .dest(na::Point2::new(camera.origin.0, camera.origin.1))
+            .scale(na::Vector2::new(camera.scale, camera.scale));
+        graphics::push_transform(self.context, Some(transform.to_matrix()));
+        graphics::apply_transformations(self.context).expect("expected camera transform");
+    }
+
+    pub fn end_board(&mut self) {
+        graphics::pop_transform(self.context);
+        graphics::apply_transformations(self.context).expect("expected camera transform");
+    }
+
+    // Background of the HUD, covering whatever part of the level is beyond the view
+    pub fn draw_hud_panel(&mut self, rect: layout::Rect) {
+        if rect.w <= 0.0 || rect.h <= 0.0 {
+            return;
+        }
+        let rect = Rect::new(rect.x, rect.y, rect.w, rect.h);
+        let mesh = graphics::Mesh::new_rectangle(
+            self.context,
+            graphics::DrawMode::fill(),
+            rect,
+            Color::new(0.95, 0.95, 0.95, 1.0),
+        )
+            .expect("expected HUD mesh");
+
+        graphics::draw(self.context, &mesh, graphics::DrawParam::new()).expect("expected drawing HUD panel");
+    }
+
+    // Dims the board under a menu
+    pub fn draw_overlay(&mut self) {
+        let rect = graphics::screen_coordinates(self.context);
+        let mesh = graphics::Mesh::new_rectangle(
+            self.context,
+            graphics::DrawMode::fill(),
+            rect,
+            Color::new(0.95, 0.95, 0.95, 0.85),
+        )
+            .expect("expected overlay mesh");
+
+        graphics::draw(self.context, &mesh, graphics::DrawParam::new()).expect("expected drawing overlay");
+    }
+
+    // How many lines of text fit on the screen
+    pub fn visible_lines(&mut self) -> usize {
+        ((graphics::screen_coordinates(self.context).h - TILE_SIZE) / LINE_HEIGHT) as usize
     }
 }
 
 // System implementation
 impl<'a> System<'a> for RenderingSystem<'a> {
     // Data
-    type SystemData = (Read<'a, Gameplay>, Read<'a, Time>, ReadStorage<'a, Position>, ReadStorage<'a, Renderable>);
+    type SystemData = (
+        Read<'a, Gameplay>,
+        Read<'a, Collection>,
+        Read<'a, LevelLoadError>,
+        Read<'a, Hint>,
+        Read<'a, Replay>,
+        Read<'a, Profile>,
+        Read<'a, Screens>,
+        Read<'a, MoveHistory>,
+        Read<'a, Editor>,
+        Read<'a, Route>,
+        Read<'a, Time>,
+        Read<'a, Bindings>,
+        Read<'a, Animation>,
+        Read<'a, Camera>,
+        Read<'a, Layout>,
+        Read<'a, Skins>,
+        Write<'a, TextureCache>,
+        Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Renderable>,
+        ReadStorage<'a, Deadlocked>,
+        ReadStorage<'a, Motion>,
+    );
 
     fn run(&mut self, data: Self::SystemData) {
-        let (gameplay, time, positions, renderables) = data;
+        let (gameplay,
+            collection,
+            load_error,
+            hint,
+            replay,
+            profile,
+            screens,
+            history,
+            editor,
+            route,
+            time,
+            bindings,
+            animation,
+            camera,
+            layout,
+            skins,
+            mut textures,
+            entities,
+            positions,
+            renderables,
+            deadlocked,
+            motions) = data;
 
         // Clearing the screen (this gives us the backround colour)
         graphics::clear(self.context, graphics::Color::new(0.95, 0.95, 0.95, 1.0));
 
+        // Title and level list take the whole window
+        let screen = screens.current();
+        let mut lines = Vec::new();
+        match screen {
+            Screen::Title { selected } => {
+                lines.push(("Rust Sokoban!".to_string(), text_color()));
+                lines.push((collection.title.clone(), faded_color()));
+                lines.push((String::new(), text_color()));
+                lines.extend(menu_lines(screen.menu(), selected));
+                lines.push((String::new(), text_color()));
+                let choose = format!(
+                    "{}/{} and {} to choose",
+                    key_or(&bindings, Action::Move(Direction::Up), "Up"),
+                    key_or(&bindings, Action::Move(Direction::Down), "Down"),
+                    key_or(&bindings, Action::Confirm, "Return"),
+                );
+                lines.push((choose, faded_color()));
+            }
+            Screen::LevelSelect { selected } => {
+                lines.push((format!("{} - choose a level", collection.title), text_color()));
+                lines.push((String::new(), text_color()));
+
+                // Scroll so the highlighted level stays in sight
+                let rows = self.visible_lines().saturating_sub(4).max(1);
+                let first = selected.saturating_sub(rows / 2).min(collection.levels.len().saturating_sub(rows));
+                for (index, level) in collection.levels.iter().enumerate().skip(first).take(rows) {
+                    let solved = match profile.score(&collection.name, &level.title) {
+                        Some(score) => format!("[x] {}. {} - {} moves, {} pushes", index + 1, level.title, score.moves, score.pushes),
+                        None => format!("[ ] {}. {}", index + 1, level.title),
+                    };
+                    lines.push(if index == selected {
+                        (format!("> {}", solved), selected_color())
+                    } else {
+                        (format!("  {}", solved), text_color())
+                    });
+                }
+
+                lines.push((String::new(), text_color()));
+                let play = format!(
+                    "{} to play, {} to go back",
+                    key_or(&bindings, Action::Confirm, "Return"),
+                    key_or(&bindings, Action::Back, "Escape"),
+                );
+                lines.push((play, faded_color()));
+            }
+            Screen::Controls { selected, waiting } => {
+                lines.push(("Controls".to_string(), text_color()));
+                lines.push((String::new(), text_color()));
+
+                let rows = self.visible_lines().saturating_sub(4).max(1);
+                let entries = BINDABLE_ACTIONS.len() + 1;
+                let first = selected.saturating_sub(rows / 2).min(entries.saturating_sub(rows));
+                for index in (0..entries).skip(first).take(rows) {
+                    let entry = match BINDABLE_ACTIONS.get(index) {
+                        Some((_, label, _)) if waiting && index == selected => format!("{}: press a key", label),
+                        Some((_, label, _)) => format!("{}: {}", label, bindings.describe(index)),
+                        None => "Reset to defaults".to_string(),
+                    };
+                    lines.push(if index == selected {
+                        (format!("> {}", entry), selected_color())
+                    } else {
+                        (format!("  {}", entry), text_color())
+                    });
+                }
+
+                lines.push((String::new(), text_color()));
+                let confirm = key_or(&bindings, Action::Confirm, "Return");
+                let bind = format!("{}, then a key or button to add or remove it", confirm);
+                lines.push((bind, faded_color()));
+            }
+            _ => (),
+        }
+        if !screen.shows_board() {
+            self.draw_lines(&lines);
+            graphics::present(self.context).expect("expected to present");
+            return;
+        }
+
+        // The board goes where the camera says, the HUD stays put
+        self.begin_board(&camera);
+
         // Get all the renderables with their positions.
-        let rendering_data = (&positions, &renderables).join().collect::<Vec<_>>();
-        let mut rendering_batches: HashMap<u8, HashMap<String, Vec<DrawParam>>> = HashMap::new();
+        let rendering_data = (&entities, &positions, &renderables).join().collect::<Vec<_>>();
+        let mut rendering_batches: RenderingBatches = HashMap::new();
+        let skin = skins.current();
+
+        // Iterate each of the renderables, ask the skin which image (and which
+        // part of it) should be rendered, and then add that to the rendering_batches.
+        for (entity, position, renderable) in rendering_data.iter() {
+            // Moving entities are drawn on their way between two tiles
+            let (x, y, frame) = match motions.get(*entity) {
+                Some(motion) => animation.place(motion, (position.x, position.y), time.delta),
+                None => (position.x as f32, position.y as f32, 0),
+            };
 
-        // Iterate each of the renderables, determine which image path should be rendered
-        // at which drawparams, and then add that to the rendering_batches.
-        for (position, renderable) in rendering_data.iter() {
-            // Load the image
-            let image_path = self.get_image(renderable, time.delta);
+            let sprite = skin.sprite(renderable.role, frame);
 
-            let x = position.x as f32 * TILE_SIZE;
-            let y = position.y as f32 * TILE_SIZE;
+            let x = x * TILE_SIZE;
+            let y = y * TILE_SIZE;
             let z = position.z;
 
             // Add to rendering batches
@@ -85,33 +329,144 @@ impl<'a> System<'a> for RenderingSystem<'a> {
             rendering_batches
                 .entry(z)
                 .or_default()
-                .entry(image_path)
+                .entry(sprite.image)
                 .or_default()
-                .push(draw_param);
+                .push((draw_param, sprite.source));
         }
 
-        // Iterate spritebatches ordered by z and actually render each of them
-        for (_z, group) in rendering_batches
-            .iter()
-            .sorted_by(|a, b| Ord::cmp(&a.0, &b.0))
-        {
+        // Hand each layer and image its sprites, the cache only rebuilds the
+        // batches whose sprites changed since the last frame
+        for (z, group) in rendering_batches.iter() {
             for (image_path, draw_params) in group {
-                let image = Image::new(self.context, image_path).expect("expected image");
-                let mut sprite_batch = SpriteBatch::new(image);
-
-                for draw_param in draw_params.iter() {
-                    sprite_batch.add(*draw_param);
-                }
+                // Only fails when not even the placeholder can be made, the
+                // sprites are left out then
+                let (width, height) = match textures.image(self.context, image_path) {
+                    Ok(image) => (image.width() as f32, image.height() as f32),
+                    Err(_) => continue,
+                };
 
-                graphics::draw(self.context, &sprite_batch, graphics::DrawParam::new())
-                    .expect("expected render");
+                // Tiles fill a board tile whatever size the skin draws them
+                // at, sheet cells are cut out of the whole image
+                let sprites = draw_params
+                    .iter()
+                    .map(|(draw_param, source)| match source {
+                        Some(source) => draw_param
+                            .src(Rect::new(source.x / width, source.y / height, source.w / width, source.h / height))
+                            .scale(na::Vector2::new(TILE_SIZE / source.w, TILE_SIZE / source.h)),
+                        None => draw_param.scale(na::Vector2::new(TILE_SIZE / width, TILE_SIZE / height)),
+                    })
+                    .collect();
+                // The image is in the cache by now, so this has nothing left to fail on
+                let _ = textures.update_batch(self.context, *z, image_path, sprites);
             }
         }
+        textures.retain_batches(|z, path| rendering_batches.get(&z).is_some_and(|group| group.contains_key(path)));
+
+        // Render the batches ordered by z
+        for sprite_batch in textures.batches() {
+            graphics::draw(self.context, sprite_batch, graphics::DrawParam::new()).expect("expected render");
+        }
+
+        // Highlight the tile the hint says to step on
+        if let HintStatus::Move { x, y, .. } = hint.status {
+            self.draw_highlight(x, y, Color::new(1.0, 0.85, 0.0, 0.5));
+        }
+
+        // The box picked to be pushed where the next click goes
+        if let Some((x, y)) = route.selected {
+            self.draw_highlight(x, y, Color::new(0.1, 0.3, 0.9, 0.4));
+        }
+
+        // Mark the boxes that can't be saved any more
+        for (position, _deadlocked) in (&positions, &deadlocked).join() {
+            self.draw_highlight(position.x, position.y, Color::new(1.0, 0.0, 0.0, 0.4));
+        }
+
+        if screen == Screen::Editor {
+            self.draw_highlight(editor.cursor.0, editor.cursor.1, Color::new(0.1, 0.3, 0.9, 0.4));
+        }
+        self.end_board();
+
+        self.draw_hud_panel(layout.hud);
+
+        // The editor shows its cursor and brush instead of the game state
+        if screen == Screen::Editor {
+            self.draw_text("Level editor", 0, &layout);
+            self.draw_text(&format!("Brush: {}", editor.brush()), 1, &layout);
+            self.draw_text(&format!("Size: {}x{}", editor.map.width, editor.map.height), 2, &layout);
+            let key = |action| bindings.editor_key_name(action).unwrap_or_else(|| "-".to_string());
+            let brushes = [Action::SelectBrush(0), Action::SelectBrush(8), Action::SelectBrush(9), Action::NextBrush];
+            let [first, ninth, tenth, next] = brushes.map(key);
+            self.draw_text(&format!("{}-{}, {}, {}: brush", first, ninth, tenth, next), 3, &layout);
+            self.draw_text(&format!("{}, mouse: paint", key(Action::Paint)), 4, &layout);
+            let [left, up, right, down] = Direction::ALL.map(|direction| key(Action::Move(direction)));
+            self.draw_text(&format!("Shift/Ctrl+{}/{}/{}/{}: size", up, down, left, right), 5, &layout);
+            let saving = [Action::Validate, Action::TestPlay, Action::SaveLevel, Action::SaveXsb];
+            let [validate, test, save, xsb] = saving.map(key);
+            self.draw_text(&format!("{} check, {} test, {}/{} save", validate, test, save, xsb), 6, &layout);
+            self.draw_text(&editor.message, 7, &layout);
+            graphics::present(self.context).expect("expected to present");
+            return;
+        }
 
         // Render any text
-        self.draw_text(&format!("State: {}", gameplay.state), 0);
-        self.draw_text(&format!("Moves: {}", gameplay.moves_count), 1);
-        self.draw_text(&format!("FPS: {:.0}", timer::fps(self.context)), 2);
+        let level = collection.levels.get(gameplay.level);
+        if let Some(level) = level {
+            self.draw_text(&level.title, 0, &layout);
+        }
+        self.draw_text(&format!("State: {}", gameplay.state), 1, &layout);
+        let best = level.and_then(|level| profile.score(&collection.name, &level.title));
+        let moves = match best {
+            Some(best) => format!("Moves: {} (best {})", gameplay.moves_count, best.moves),
+            None => format!("Moves: {}", gameplay.moves_count),
+        };
+        self.draw_text(&moves, 2, &layout);
+        self.draw_text(&format!("FPS: {:.0}", timer::fps(self.context)), 3, &layout);
+        self.draw_text(&hint.status.to_string(), 4, &layout);
+        self.draw_text(&replay.to_string(), 5, &layout);
+        self.draw_text(&format!("Camera: {} x{:.2}", camera.mode, camera.scale), 6, &layout);
+        self.draw_text(&format!("Skin: {}", skin.name), 7, &layout);
+
+        // A level that failed to load has no entities, explain why instead
+        if let Some(error) = &load_error.error {
+            self.draw_error(&error.to_string(), &bindings);
+        }
+
+        // Menus over the board
+        match screen {
+            Screen::Paused { selected } => {
+                lines.push(("Paused".to_string(), text_color()));
+                lines.push((String::new(), text_color()));
+                lines.extend(menu_lines(screen.menu(), selected));
+            }
+            Screen::WonSummary => {
+                let pushes = history.undo_stack.iter().filter(|record| record.is_push()).count();
+                let seconds = gameplay.elapsed(time.delta).as_secs_f64();
+                lines.push(("Level solved!".to_string(), text_color()));
+                if let Some(level) = level {
+                    lines.push((level.title.clone(), faded_color()));
+                }
+                lines.push((String::new(), text_color()));
+                lines.push((format!("Moves: {}", gameplay.moves_count), text_color()));
+                lines.push((format!("Pushes: {}", pushes), text_color()));
+                lines.push((format!("Time: {:.1}s", seconds), text_color()));
+                if let Some(best) = best {
+                    lines.push((format!("Best: {} moves, {} pushes", best.moves, best.pushes), text_color()));
+                }
+                lines.push((String::new(), text_color()));
+                let go_on = format!(
+                    "{} to go on, {} for the levels",
+                    key_or(&bindings, Action::Confirm, "Return"),
+                    key_or(&bindings, Action::Back, "Escape"),
+                );
+                lines.push((go_on, faded_color()));
+            }
+            _ => (),
+        }
+        if !lines.is_empty() {
+            self.draw_overlay();
+            self.draw_lines(&lines);
+        }
 
         // Finally, present the context, this will actually display everything
         // on the screen.
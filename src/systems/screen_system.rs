@@ -0,0 +1,157 @@
+use specs::{Read, System, Write};
+
+use crate::actions::Action;
+use crate::bindings::{Bindings, BINDABLE_ACTIONS};
+use crate::collection::Collection;
+use crate::components::Direction;
+use crate::editor::Editor;
+use crate::levels;
+use crate::resources::{Gameplay, InputQueue, LevelChange, LevelQueue};
+use crate::screens::{MenuItem, Screen, Screens};
+
+// How many levels up or down the list Left and Right jump
+const LEVEL_PAGE: usize = 10;
+
+pub struct ScreenSystem {}
+
+// System implementation
+impl<'a> System<'a> for ScreenSystem {
+    // Data
+    type SystemData = (
+        Write<'a, InputQueue>,
+        Write<'a, Screens>,
+        Write<'a, LevelQueue>,
+        Read<'a, Gameplay>,
+        Read<'a, Collection>,
+        Write<'a, Bindings>,
+        Read<'a, Editor>,
+    );
+
+    fn run(&mut self, data: Self::SystemData) {
+        let (mut input_queue, mut screens, mut level_queue, gameplay, collection, mut bindings, editor) = data;
+
+        // The board and the editor take their own input
+        let screen = screens.current();
+        if screen == Screen::Playing || screen == Screen::Editor {
+            return;
+        }
+
+        // The key or button is given to the action instead of doing anything
+        if let Screen::Controls { selected, waiting: true } = screen {
+            if bindings.bind_captured(selected) {
+                screens.replace(Screen::Controls { selected, waiting: false });
+            }
+            return;
+        }
+
+        let action = match input_queue.pop_front() {
+            Some(action) => action,
+            None => return,
+        };
+
+        match screen {
+            Screen::Title { selected } | Screen::Paused { selected } => {
+                let menu = screen.menu();
+                let selected = match action {
+                    Action::Move(Direction::Up) => (selected + menu.len() - 1) % menu.len(),
+                    Action::Move(Direction::Down) => (selected + 1) % menu.len(),
+                    Action::Confirm => {
+                        let item = menu[selected];
+                        match item {
+                            MenuItem::Play => screens.push(Screen::Playing),
+                            MenuItem::Editor => screens.push(Screen::Editor),
+                            MenuItem::Controls => screens.push(Screen::Controls { selected: 0, waiting: false }),
+                            MenuItem::Quit => screens.quit = true,
+                            MenuItem::Resume => screens.pop(),
+                            MenuItem::Restart => {
+                                screens.pop();
+                                level_queue.change = Some(LevelChange::Restart);
+                            }
+                            MenuItem::Levels => {
+                                // The list takes the place of the board
+                                if let Screen::Paused { .. } = screen {
+                                    screens.pop();
+                                    screens.pop();
+                                }
+                                screens.push(Screen::LevelSelect { selected: gameplay.level });
+                            }
+                            MenuItem::Title => screens.stack = vec![Screen::Title { selected: 0 }],
+                        }
+                        return;
+                    }
+                    Action::Back => {
+                        if let Screen::Paused { .. } = screen {
+                            screens.pop();
+                        }
+                        return;
+                    }
+                    _ => return,
+                };
+                screens.replace(match screen {
+                    Screen::Title { .. } => Screen::Title { selected },
+                    _ => Screen::Paused { selected },
+                });
+            }
+            Screen::LevelSelect { selected } => {
+                let last = collection.levels.len().saturating_sub(1);
+                let selected = match action {
+                    Action::Move(Direction::Up) => selected.saturating_sub(1),
+                    Action::Move(Direction::Down) => (selected + 1).min(last),
+                    Action::Move(Direction::Left) => selected.saturating_sub(LEVEL_PAGE),
+                    Action::Move(Direction::Right) => (selected + LEVEL_PAGE).min(last),
+                    Action::Confirm => {
+                        if selected <= last && !collection.levels.is_empty() {
+                            level_queue.change = Some(LevelChange::Select(selected));
+                            screens.replace(Screen::Playing);
+                        }
+                        return;
+                    }
+                    Action::Back => {
+                        screens.pop();
+                        return;
+                    }
+                    _ => return,
+                };
+                screens.replace(Screen::LevelSelect { selected });
+            }
+            // The last entry puts the default keys back
+            Screen::Controls { selected, .. } => {
+                let entries = BINDABLE_ACTIONS.len() + 1;
+                let selected = match action {
+                    Action::Move(Direction::Up) => (selected + entries - 1) % entries,
+                    Action::Move(Direction::Down) => (selected + 1) % entries,
+                    Action::Confirm if selected == BINDABLE_ACTIONS.len() => {
+                        bindings.reset();
+                        return;
+                    }
+                    Action::Confirm => {
+                        screens.replace(Screen::Controls { selected, waiting: true });
+                        return;
+                    }
+                    Action::Back => {
+                        screens.pop();
+                        return;
+                    }
+                    _ => return,
+                };
+                screens.replace(Screen::Controls { selected, waiting: false });
+            }
+            // Confirm moves on without waiting, Back goes to the level list. A
+            // test play goes back to the editor either way.
+            Screen::WonSummary => match action {
+                Action::Confirm | Action::Back if editor.stashed.is_some() => {
+                    levels::leave_summary(&mut screens, &mut level_queue, gameplay.level, collection.levels.len(), true)
+                }
+                Action::Confirm => {
+                    levels::leave_summary(&mut screens, &mut level_queue, gameplay.level, collection.levels.len(), false)
+                }
+                Action::Back => {
+                    screens.pop();
+                    screens.replace(Screen::LevelSelect { selected: gameplay.level });
+                }
+                _ => (),
+            },
+            Screen::Playing | Screen::Editor => (),
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::{BTreeMap, HashMap};
+
+use ggez::graphics::spritebatch::SpriteBatch;
+use ggez::graphics::{Color, DrawParam, Image};
+use ggez::{Context, GameResult};
+use specs::{Join, World, WorldExt};
+
+use crate::components::Renderable;
+use crate::resources::MapLoads;
+use crate::skins::Skins;
+
+// What images that cannot be read are drawn as, loud enough to be noticed
+const PLACEHOLDER_SIZE: u16 = 32;
+const PLACEHOLDER_COLOUR: Color = Color { r: 1.0, g: 0.0, b: 1.0, a: 1.0 };
+
+// Images loaded once instead of every frame, and a sprite batch per layer and
+// image that is only rebuilt when its sprites change
+#[derive(Default)]
+pub struct TextureCache {
+    images: HashMap<String, Image>,
+    batches: BTreeMap<(u8, String), (Vec<DrawParam>, SpriteBatch)>,
+    // Skin the images were loaded for
+    skin: String,
+    // Map load the images were read for, a new map may need images not loaded
+    // yet. None until the first and after clearing.
+    loaded_for: Option<u64>,
+    // Batches rebuilt so far, for the benchmark
+    pub rebuilds: u64,
+    // Images that could not be read since the front end last asked
+    missing: Vec<String>,
+}
+
+impl TextureCache {
+    // The image at the given resource path, read from disk the first time.
+    // An image that cannot be read is noted and a placeholder drawn instead.
+    pub fn image(&mut self, context: &mut Context, path: &str) -> GameResult<&Image> {
+        if !self.images.contains_key(path) {
+            let image = match Image::new(context, path) {
+                Ok(image) => image,
+                Err(error) => {
+                    self.missing.push(format!("{}: {}", path, error));
+                    Image::solid(context, PLACEHOLDER_SIZE, PLACEHOLDER_COLOUR)?
+                }
+            };
+            self.images.insert(path.to_string(), image);
+        }
+        Ok(&self.images[path])
+    }
+
+    // Gives the batch of a layer and image the sprites it should hold,
+    // rebuilding it only if they differ from last time
+    pub fn update_batch(&mut self, context: &mut Context, z: u8, path: &str, sprites: Vec<DrawParam>) -> GameResult {
+        let key = (z, path.to_string());
+        if self.batches.get(&key).is_some_and(|(drawn, _)| *drawn == sprites) {
+            return Ok(());
+        }
+
+        let image = self.image(context, path)?.clone();
+        let (drawn, batch) = self.batches.entry(key).or_insert_with(|| (Vec::new(), SpriteBatch::new(image)));
+        batch.clear();
+        for sprite in sprites.iter() {
+            batch.add(*sprite);
+        }
+        *drawn = sprites;
+        self.rebuilds += 1;
+        Ok(())
+    }
+
+    // Drops the batches of images nothing is drawn with any more
+    pub fn retain_batches(&mut self, keep: impl Fn(u8, &str) -> bool) {
+        self.batches.retain(|(z, path), _| keep(*z, path));
+    }
+
+    // Batches from the lowest layer up
+    pub fn batches(&self) -> impl Iterator<Item = &SpriteBatch> {
+        self.batches.values().map(|(_, batch)| batch)
+    }
+
+    // Forgets every image and batch, they are read again when needed
+    pub fn clear(&mut self) {
+        self.images.clear();
+        self.batches.clear();
+        self.skin.clear();
+        self.loaded_for = None;
+    }
+}
+
+// Loads every image the tiles of the level can show with the current skin,
+// when a level was loaded or the skin switched. Returns the images that could
+// not be read since the last call, for the front end to report.
+pub fn update_textures(world: &mut World, context: &mut Context) -> Vec<String> {
+    let skins = world.read_resource::<Skins>();
+    let mut cache = world.write_resource::<TextureCache>();
+    let skin = skins.current();
+    let loads = world.read_resource::<MapLoads>().count;
+    if cache.loaded_for == Some(loads) && cache.skin == skin.id {
+        return std::mem::take(&mut cache.missing);
+    }
+    if cache.skin != skin.id {
+        cache.batches.clear();
+        cache.skin = skin.id.clone();
+    }
+    cache.loaded_for = Some(loads);
+
+    let renderables = world.read_storage::<Renderable>();
+    let mut roles = Vec::new();
+    for renderable in renderables.join() {
+        if !roles.contains(&renderable.role) {
+            roles.push(renderable.role);
+        }
+    }
+    for role in roles {
+        for frame in 0..skin.frame_count(role) {
+            let path = skin.sprite(role, frame).image;
+            if let Err(error) = cache.image(context, &path) {
+                cache.missing.push(format!("{}: {}", path, error));
+            }
+        }
+    }
+    std::mem::take(&mut cache.missing)
+}